@@ -0,0 +1,15 @@
+extern crate base64;
+extern crate rucry;
+
+use rucry::rsa;
+
+const SECRET_B64: &str = "VGhhdCdzIHdoeSBJIGZvdW5kIHlvdSBkb24ndCBwbGF5IGFyb3VuZCB3aXRoIHRoZSBGdW5reSBDb2xkIE1lZGluYQ==";
+
+fn main() {
+    let oracle = rsa::ParityOracle::new(1024);
+    let secret = base64::decode(SECRET_B64).unwrap();
+    let c = oracle.public_key().encrypt(&rsa::bytes_to_int(&secret));
+
+    let m = rsa::parity_oracle_attack(&oracle, &c, rsa::hollywood_progress);
+    println!("Decrypted as:\n\"{}\"", String::from_utf8_lossy(&rsa::int_to_bytes(&m)));
+}
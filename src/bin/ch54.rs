@@ -0,0 +1,41 @@
+extern crate rucry;
+
+use std::io;
+use std::path::Path;
+
+use rucry::block::BLOCK_SIZE;
+use rucry::herding::DiamondStructure;
+use rucry::mdhash::MdHash;
+use rucry::util;
+
+const DIAMOND_FILE: &str = "diamond.txt";
+const STATE_BITS: usize = 24;
+const DEPTH: usize = 8;
+const PREFIX: &[u8] = b"2018 World Cup final: France 4, Croatia 2";
+
+fn main() {
+    let hash = MdHash::with_state_bits(STATE_BITS);
+    let path = Path::new(DIAMOND_FILE);
+    let diamond = match DiamondStructure::load(path, &hash) {
+        Ok(diamond) => diamond,
+        Err(err) => {
+            if err.kind() != io::ErrorKind::NotFound {
+                println!("Ignoring {}: {}", DIAMOND_FILE, err);
+            }
+            println!("Building diamond structure of depth {}...", DEPTH);
+            let diamond = DiamondStructure::build(&hash, DEPTH);
+            diamond.save(path).expect("Could not save diamond structure");
+            println!("Built with {} compression function calls, saved to {}", hash.compressions(), DIAMOND_FILE);
+            diamond
+        }
+    };
+
+    let prefix_blocks = PREFIX.len().div_ceil(BLOCK_SIZE);
+    let prediction = diamond.predicted_hash(&hash, prefix_blocks);
+    println!("Prediction committed: {}", util::binary_to_hex(&prediction));
+
+    hash.reset_compressions();
+    let message = diamond.herd(&hash, PREFIX);
+    println!("Herded message found with {} compression function calls:\n{}", hash.compressions(), util::binary_to_hex(&message));
+    println!("Hash: {}", util::binary_to_hex(&hash.hash(&message)));
+}
@@ -0,0 +1,25 @@
+extern crate base64;
+extern crate rucry;
+
+use std::thread;
+
+use rucry::rc4;
+
+const COOKIE_B64: &str = "QkUgU1VSRSBUTyBEUklOSyBZT1VSIE9WQUxUSU5F";
+const SAMPLES: usize = 1 << 24;
+
+fn main() {
+    let cookie = base64::decode(COOKIE_B64).unwrap();
+    let oracle = rc4::CookieOracle::new(&cookie);
+    let threads = thread::available_parallelism().map(|n| n.get()).unwrap_or(1);
+
+    let candidates = rc4::recover_cookie(&oracle, &[rc4::Z16_BIAS, rc4::Z32_BIAS], SAMPLES, threads);
+    for (i, byte_candidates) in candidates.iter().enumerate() {
+        let top: Vec<String> = byte_candidates.iter()
+            .take(3)
+            .map(|c| format!("{:?} ({:.6})", c.byte as char, c.likelihood))
+            .collect();
+        println!("{:2}: {}", i, top.join(", "));
+    }
+    println!("Recovered: \"{}\"", String::from_utf8_lossy(&rc4::best_guess(&candidates)));
+}
@@ -0,0 +1,41 @@
+extern crate num_bigint;
+extern crate rucry;
+
+use std::time::Instant;
+
+use num_bigint::BigUint;
+
+use rucry::dlog;
+use rucry::dlog::{DlogStats, KangarooConfig, ModP};
+use rucry::subgroup::SubgroupParams;
+use rucry::util;
+
+fn main() {
+    let params = SubgroupParams::partially_smooth();
+    let group = ModP { p: &params.dh.p };
+    let g = &params.dh.g;
+    let a = BigUint::from(0u32);
+
+    println!("{:>5} {:>10} {:>12} {:>8} {:>10} {:>12} {:>8}", "bits", "kangaroo", "iterations", "table", "bsgs", "iterations", "table");
+    for bits in (16..=32).step_by(4) {
+        let b = BigUint::from(1u64 << bits);
+        let x = util::generate_random_biguint_below(&b);
+        let y = g.modpow(&x, &params.dh.p);
+
+        let mut kangaroo_stats = DlogStats::default();
+        let start = Instant::now();
+        let config = KangarooConfig::for_interval(&a, &b);
+        let found = dlog::kangaroo_in(&group, g, &y, &a, &b, &config, &mut kangaroo_stats);
+        let kangaroo_time = start.elapsed();
+        let kangaroo_result = if found == Some(x.clone()) { format!("{:.2?}", kangaroo_time) } else { "missed".to_string() };
+
+        let mut bsgs_stats = DlogStats::default();
+        let start = Instant::now();
+        assert_eq!(Some(x), dlog::baby_step_giant_step_in(&group, g, &y, &a, &b, &mut bsgs_stats));
+        let bsgs_time = start.elapsed();
+
+        println!("{:>5} {:>10} {:>12} {:>8} {:>10.2?} {:>12} {:>8}", bits,
+                 kangaroo_result, kangaroo_stats.iterations, kangaroo_stats.table_size,
+                 bsgs_time, bsgs_stats.iterations, bsgs_stats.table_size);
+    }
+}
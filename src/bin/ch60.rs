@@ -0,0 +1,23 @@
+extern crate rucry;
+
+use std::time::Instant;
+
+use rucry::dlog::DlogStats;
+use rucry::montgomery::MontgomeryCurve;
+use rucry::twist;
+use rucry::twist::XOnlyResponder;
+
+fn main() {
+    let curve = MontgomeryCurve::cryptopals();
+    let responder = XOnlyResponder::new(&curve);
+    println!("Twist order: {}", curve.twist_order());
+    println!("Usable factors: {:?}", twist::twist_factors(&curve, twist::DEFAULT_FACTOR_BOUND));
+
+    let start = Instant::now();
+    let mut stats = DlogStats::default();
+    match twist::twist_attack(&curve, &responder, twist::DEFAULT_FACTOR_BOUND, &curve.n, &mut stats) {
+        Some(k) => println!("Recovered private key {} (or n minus it) in {:.2?}", k, start.elapsed()),
+        None => println!("Attack failed after {:.2?}", start.elapsed()),
+    }
+    println!("{} oracle queries, {} kangaroo iterations", responder.queries(), stats.iterations);
+}
@@ -0,0 +1,21 @@
+extern crate rucry;
+
+use std::time::Instant;
+
+use rucry::block;
+use rucry::truncgcm;
+use rucry::truncgcm::TruncatedGcm;
+
+fn main() {
+    let receiver = TruncatedGcm::new(truncgcm::TRUNCATED_TAG_SIZE);
+    let plaintext = vec![0u8; truncgcm::blocks_needed(truncgcm::DEFAULT_FREE_BLOCKS).unwrap() * block::BLOCK_SIZE];
+    let msg = receiver.encrypt(&plaintext, b"");
+    println!("{}-bit tag over {} ciphertext blocks", msg.tag.len() * 8, plaintext.len() / block::BLOCK_SIZE);
+
+    let start = Instant::now();
+    match truncgcm::recover_authentication_key(&receiver, &msg, truncgcm::DEFAULT_FREE_BLOCKS, truncgcm::MAX_QUERIES_PER_ROUND) {
+        Some(h) => println!("Recovered H = {:032x} (correct: {}) in {:.2?}", h.0, receiver.is_authentication_key(h), start.elapsed()),
+        None => println!("Attack failed after {:.2?}", start.elapsed()),
+    }
+    println!("{} forgery attempts", receiver.queries());
+}
@@ -0,0 +1,246 @@
+use std::ops::{Add, Mul};
+
+use rand::{thread_rng, Rng};
+
+use gf128::Gf128;
+
+const WORD_BITS: usize = 64;
+
+/// Dense matrix over GF(2), every row packed into u64 words
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct BitMatrix {
+    rows: usize,
+    cols: usize,
+    data: Vec<Vec<u64>>,
+}
+
+impl BitMatrix {
+    pub fn new(rows: usize, cols: usize) -> BitMatrix {
+        BitMatrix { rows, cols, data: vec![vec![0; cols.div_ceil(WORD_BITS)]; rows] }
+    }
+
+    pub fn identity(n: usize) -> BitMatrix {
+        let mut m = BitMatrix::new(n, n);
+        for i in 0..n {
+            m.set(i, i, true);
+        }
+        m
+    }
+
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+
+    pub fn cols(&self) -> usize {
+        self.cols
+    }
+
+    pub fn get(&self, row: usize, col: usize) -> bool {
+        (self.data[row][col / WORD_BITS] >> (col % WORD_BITS)) & 1 == 1
+    }
+
+    pub fn set(&mut self, row: usize, col: usize, value: bool) {
+        let mask = 1u64 << (col % WORD_BITS);
+        if value {
+            self.data[row][col / WORD_BITS] |= mask;
+        } else {
+            self.data[row][col / WORD_BITS] &= !mask;
+        }
+    }
+
+    pub fn row(&self, row: usize) -> &[u64] {
+        &self.data[row]
+    }
+
+    /// Appends a row given as packed words, `cols` bits long
+    pub fn push_row(&mut self, row: &[u64]) {
+        assert_eq!(self.cols.div_ceil(WORD_BITS), row.len());
+        self.data.push(row.to_vec());
+        self.rows += 1;
+    }
+
+    /// Rows `start..end` as a new matrix
+    pub fn row_range(&self, start: usize, end: usize) -> BitMatrix {
+        BitMatrix { rows: end - start, cols: self.cols, data: self.data[start..end].to_vec() }
+    }
+
+    pub fn transpose(&self) -> BitMatrix {
+        let mut t = BitMatrix::new(self.cols, self.rows);
+        for r in 0..self.rows {
+            for c in 0..self.cols {
+                if self.get(r, c) {
+                    t.set(c, r, true);
+                }
+            }
+        }
+        t
+    }
+
+    /// The field element as a 128-bit column vector, entry i being the coefficient of x^i
+    pub fn mul_element(&self, v: Gf128) -> Gf128 {
+        assert_eq!(128, self.cols);
+        let words = [v.0 as u64, (v.0 >> 64) as u64];
+        let mut result = 0u128;
+        for r in 0..self.rows {
+            let parity = self.data[r].iter().zip(&words).fold(0, |acc, (a, b)| acc ^ (a & b).count_ones());
+            result |= ((parity & 1) as u128) << r;
+        }
+        Gf128(result)
+    }
+
+    /// Reduces to row echelon form in place, returns the pivot column of every nonzero row
+    pub fn row_reduce(&mut self) -> Vec<usize> {
+        let mut pivots = Vec::new();
+        let mut next = 0;
+        for col in 0..self.cols {
+            let pivot = match (next..self.rows).find(|&r| self.get(r, col)) {
+                Some(pivot) => pivot,
+                None => continue,
+            };
+            self.data.swap(next, pivot);
+            let pivot_row = self.data[next].clone();
+            for r in 0..self.rows {
+                if r != next && self.get(r, col) {
+                    for (a, b) in self.data[r].iter_mut().zip(&pivot_row) {
+                        *a ^= b;
+                    }
+                }
+            }
+            pivots.push(col);
+            next += 1;
+            if next == self.rows {
+                break;
+            }
+        }
+        pivots
+    }
+
+    pub fn rank(&self) -> usize {
+        self.clone().row_reduce().len()
+    }
+
+    /// Basis of {v : Mv = 0}, one vector per row of the result
+    pub fn kernel(&self) -> BitMatrix {
+        let mut reduced = self.clone();
+        let pivots = reduced.row_reduce();
+        let mut basis = BitMatrix::new(0, self.cols);
+        let mut is_pivot = vec![false; self.cols];
+        for &p in &pivots {
+            is_pivot[p] = true;
+        }
+        for free in (0..self.cols).filter(|&c| !is_pivot[c]) {
+            let mut v = BitMatrix::new(1, self.cols);
+            v.set(0, free, true);
+            for (row, &p) in pivots.iter().enumerate() {
+                if reduced.get(row, free) {
+                    v.set(0, p, true);
+                }
+            }
+            basis.push_row(v.row(0));
+        }
+        basis
+    }
+
+    /// XOR of a random subset of the rows
+    pub fn random_combination(&self) -> Vec<u64> {
+        let mut rng = thread_rng();
+        let mut v = vec![0; self.cols.div_ceil(WORD_BITS)];
+        for row in &self.data {
+            if rng.gen() {
+                for (a, b) in v.iter_mut().zip(row) {
+                    *a ^= b;
+                }
+            }
+        }
+        v
+    }
+}
+
+impl<'a> Add<&'a BitMatrix> for &'a BitMatrix {
+    type Output = BitMatrix;
+
+    #[allow(clippy::suspicious_arithmetic_impl)]
+    fn add(self, other: &BitMatrix) -> BitMatrix {
+        assert_eq!((self.rows, self.cols), (other.rows, other.cols));
+        let mut sum = self.clone();
+        for (row, other_row) in sum.data.iter_mut().zip(&other.data) {
+            for (a, b) in row.iter_mut().zip(other_row) {
+                *a ^= b;
+            }
+        }
+        sum
+    }
+}
+
+/// Row r of the product is the XOR of the rows of `other` picked by the set bits of row r of `self`
+impl<'a> Mul<&'a BitMatrix> for &'a BitMatrix {
+    type Output = BitMatrix;
+
+    fn mul(self, other: &BitMatrix) -> BitMatrix {
+        assert_eq!(self.cols, other.rows);
+        let mut product = BitMatrix::new(self.rows, other.cols);
+        for r in 0..self.rows {
+            for k in 0..self.cols {
+                if self.get(r, k) {
+                    for (p, o) in product.data[r].iter_mut().zip(&other.data[k]) {
+                        *p ^= o;
+                    }
+                }
+            }
+        }
+        product
+    }
+}
+
+/// Matrix of x -> c * x on GF(2^128): column k is c * x^k
+pub fn multiplication_matrix(c: Gf128) -> BitMatrix {
+    let mut m = BitMatrix::new(128, 128);
+    for k in 0..128 {
+        let column = c * Gf128(1 << k);
+        for r in 0..128 {
+            m.set(r, k, (column.0 >> r) & 1 == 1);
+        }
+    }
+    m
+}
+
+/// Matrix of x -> x^2, linear in characteristic 2: column k is x^2k
+pub fn squaring_matrix() -> BitMatrix {
+    let mut m = BitMatrix::new(128, 128);
+    for k in 0..128 {
+        let x_k = Gf128(1 << k);
+        let column = x_k * x_k;
+        for r in 0..128 {
+            m.set(r, k, (column.0 >> r) & 1 == 1);
+        }
+    }
+    m
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn field_matrices_work() {
+        let a = Gf128::random();
+        let c = Gf128::random();
+        assert_eq!(c * a, multiplication_matrix(c).mul_element(a));
+        assert_eq!(a * a, squaring_matrix().mul_element(a));
+        let product = &multiplication_matrix(c) * &squaring_matrix();
+        assert_eq!(c * a * a, product.mul_element(a));
+    }
+
+    #[test]
+    fn kernel_works() {
+        let mut m = BitMatrix::new(3, 5);
+        for &(r, c) in &[(0, 0), (0, 1), (1, 1), (1, 2), (2, 0), (2, 2), (0, 4), (2, 4)] {
+            m.set(r, c, true);
+        }
+        assert_eq!(2, m.rank());
+        let kernel = m.kernel();
+        assert_eq!(3, kernel.rows());
+        assert!((&m * &kernel.transpose()).data.iter().all(|row| row.iter().all(|&w| w == 0)));
+        assert_eq!(3, kernel.rank());
+    }
+}
@@ -1,3 +1,4 @@
+use std::cmp;
 use std::u8;
 
 use openssl::symm;
@@ -19,7 +20,7 @@ pub enum EncryptionMode {
 
 pub const BLOCK_SIZE: usize = 16;
 
-fn aes_decrypt_block(
+pub fn aes_decrypt_block(
     key: &[u8],
     data: &[u8],
 ) -> Result<Vec<u8>, ErrorStack> {
@@ -37,7 +38,7 @@ fn aes_decrypt_block(
     Ok(out)
 }
 
-fn aes_encrypt_block(
+pub fn aes_encrypt_block(
     key: &[u8],
     data: &[u8],
 ) -> Result<Vec<u8>, ErrorStack> {
@@ -174,6 +175,170 @@ pub fn aes_cbc_decrypt(key: &[u8], data: &[u8], iv: &[u8], padding: Padding) ->
     }
 }
 
+/// CTR mode with a 64-bit little endian nonce followed by a 64-bit little endian block counter
+pub fn aes_ctr_crypt(key: &[u8], nonce: u64, data: &[u8]) -> Option<Vec<u8>> {
+    let mut result = Vec::with_capacity(data.len());
+
+    for (counter, chunk) in data.chunks(BLOCK_SIZE).enumerate() {
+        let mut counter_block = Vec::with_capacity(BLOCK_SIZE);
+        counter_block.extend_from_slice(&nonce.to_le_bytes());
+        counter_block.extend_from_slice(&(counter as u64).to_le_bytes());
+        match aes_encrypt_block(key, &counter_block) {
+            Ok(keystream) => result.extend_from_slice(&util::xor_bufs(chunk, &keystream[..chunk.len()])),
+            _ => return None
+        }
+    }
+
+    Some(result)
+}
+
+/// Bits `start..start + len` of `data`, most significant bit of each byte first
+fn read_bits(data: &[u8], start: usize, len: usize) -> u128 {
+    (start..start + len).fold(0, |acc, i| (acc << 1) | ((data[i / 8] >> (7 - i % 8)) & 1) as u128)
+}
+
+fn write_bits(data: &mut [u8], start: usize, len: usize, bits: u128) {
+    for (k, i) in (start..start + len).enumerate() {
+        let bit = ((bits >> (len - 1 - k)) & 1) as u8;
+        data[i / 8] |= bit << (7 - i % 8);
+    }
+}
+
+fn aes_cfb_crypt(key: &[u8], iv: &[u8], data: &[u8], segment_bits: usize, decrypt: bool) -> Option<Vec<u8>> {
+    if segment_bits == 0 || segment_bits > BLOCK_SIZE * 8 || iv.len() != BLOCK_SIZE {
+        return None;
+    }
+    let mut iv_bytes = [0u8; BLOCK_SIZE];
+    iv_bytes.copy_from_slice(iv);
+    let mut register = u128::from_be_bytes(iv_bytes);
+    let mut result = vec![0u8; data.len()];
+    let total_bits = data.len() * 8;
+    let mut position = 0;
+
+    while position < total_bits {
+        // only the last segment of a 128-bit CFB message can be short
+        let len = cmp::min(segment_bits, total_bits - position);
+        let mut output_block = [0u8; BLOCK_SIZE];
+        match aes_encrypt_block(key, &register.to_be_bytes()) {
+            Ok(block) => output_block.copy_from_slice(&block),
+            _ => return None
+        }
+        let input = read_bits(data, position, len);
+        let output = input ^ (u128::from_be_bytes(output_block) >> (BLOCK_SIZE * 8 - len));
+        write_bits(&mut result, position, len, output);
+        let ciphertext = if decrypt { input } else { output };
+        register = register.checked_shl(len as u32).unwrap_or(0) | ciphertext;
+        position += len;
+    }
+
+    Some(result)
+}
+
+/// CFB mode feeding back `segment_bits` of ciphertext per step: 1, 8 and 128 are the usual choices.
+/// `None` for a segment size outside 1..=128 or an IV that is not a block long.
+pub fn aes_cfb_encrypt(key: &[u8], iv: &[u8], data: &[u8], segment_bits: usize) -> Option<Vec<u8>> {
+    aes_cfb_crypt(key, iv, data, segment_bits, false)
+}
+
+pub fn aes_cfb_decrypt(key: &[u8], iv: &[u8], data: &[u8], segment_bits: usize) -> Option<Vec<u8>> {
+    aes_cfb_crypt(key, iv, data, segment_bits, true)
+}
+
+/// OFB mode, the keystream is the IV encrypted over and over. `None` for an IV that is not a block long.
+pub fn aes_ofb_crypt(key: &[u8], iv: &[u8], data: &[u8]) -> Option<Vec<u8>> {
+    if iv.len() != BLOCK_SIZE {
+        return None;
+    }
+    let mut result = Vec::with_capacity(data.len());
+    let mut keystream = iv.to_vec();
+
+    for chunk in data.chunks(BLOCK_SIZE) {
+        match aes_encrypt_block(key, &keystream) {
+            Ok(block) => keystream = block,
+            _ => return None
+        }
+        result.extend_from_slice(&util::xor_bufs(chunk, &keystream[..chunk.len()]));
+    }
+
+    Some(result)
+}
+
+/// Multiplies an XTS tweak by x in GF(2^128), bytes in little endian order
+fn xts_next_tweak(tweak: &mut [u8]) {
+    let mut carry = 0;
+    for byte in tweak.iter_mut() {
+        let next_carry = *byte >> 7;
+        *byte = (*byte << 1) | carry;
+        carry = next_carry;
+    }
+    if carry == 1 {
+        tweak[0] ^= 0x87;
+    }
+}
+
+/// The tweaks E_K2(data unit) * x^j for every block of the data unit
+fn xts_tweaks(tweak_key: &[u8], data_unit: u64, blocks: usize) -> Option<Vec<Vec<u8>>> {
+    let mut unit = data_unit.to_le_bytes().to_vec();
+    unit.resize(BLOCK_SIZE, 0);
+    let mut tweak = aes_encrypt_block(tweak_key, &unit).ok()?;
+    let mut tweaks = Vec::with_capacity(blocks);
+    for _ in 0..blocks {
+        tweaks.push(tweak.clone());
+        xts_next_tweak(&mut tweak);
+    }
+    Some(tweaks)
+}
+
+/// C = E_K1(P xor T) xor T, or the inverse
+fn xts_block(key: &[u8], tweak: &[u8], block: &[u8], decrypt: bool) -> Option<Vec<u8>> {
+    let xored = util::xor_bufs(block, tweak);
+    let result = if decrypt { aes_decrypt_block(key, &xored) } else { aes_encrypt_block(key, &xored) };
+    result.ok().map(|b| util::xor_bufs(&b, tweak))
+}
+
+fn aes_xts_crypt(data_key: &[u8], tweak_key: &[u8], data_unit: u64, data: &[u8], decrypt: bool) -> Option<Vec<u8>> {
+    if data.len() < BLOCK_SIZE {
+        return None;
+    }
+    let full_blocks = data.len() / BLOCK_SIZE;
+    let tail = data.len() % BLOCK_SIZE;
+    let tweaks = xts_tweaks(tweak_key, data_unit, full_blocks + 1)?;
+    // with ciphertext stealing the last full block is handled together with the tail
+    let plain_blocks = if tail == 0 { full_blocks } else { full_blocks - 1 };
+
+    let mut result = Vec::with_capacity(data.len());
+    for (i, block) in data[..plain_blocks * BLOCK_SIZE].chunks(BLOCK_SIZE).enumerate() {
+        result.extend_from_slice(&xts_block(data_key, &tweaks[i], block, decrypt)?);
+    }
+    if tail != 0 {
+        let last = &data[plain_blocks * BLOCK_SIZE..full_blocks * BLOCK_SIZE];
+        let partial = &data[full_blocks * BLOCK_SIZE..];
+        // decryption undoes the last block first, so it takes the later tweak
+        let (first_tweak, second_tweak) = if decrypt {
+            (&tweaks[plain_blocks + 1], &tweaks[plain_blocks])
+        } else {
+            (&tweaks[plain_blocks], &tweaks[plain_blocks + 1])
+        };
+        let stolen = xts_block(data_key, first_tweak, last, decrypt)?;
+        let mut combined = partial.to_vec();
+        combined.extend_from_slice(&stolen[tail..]);
+        result.extend_from_slice(&xts_block(data_key, second_tweak, &combined, decrypt)?);
+        result.extend_from_slice(&stolen[..tail]);
+    }
+
+    Some(result)
+}
+
+/// XTS-AES-128 (IEEE 1619) of one data unit, such as a disk sector, at least a block long. Lengths that are not
+/// a multiple of the block size use ciphertext stealing.
+pub fn aes_xts_encrypt(data_key: &[u8], tweak_key: &[u8], data_unit: u64, data: &[u8]) -> Option<Vec<u8>> {
+    aes_xts_crypt(data_key, tweak_key, data_unit, data, false)
+}
+
+pub fn aes_xts_decrypt(data_key: &[u8], tweak_key: &[u8], data_unit: u64, data: &[u8]) -> Option<Vec<u8>> {
+    aes_xts_crypt(data_key, tweak_key, data_unit, data, true)
+}
+
 pub fn pad_pkcs(mut buf: Vec<u8>, block_size: usize) -> Vec<u8> {
     let desired_block_size = if block_size > buf.len() {
         block_size
@@ -194,12 +359,12 @@ pub fn pad_pkcs(mut buf: Vec<u8>, block_size: usize) -> Vec<u8> {
 
 fn unpad_pkcs(mut buf: Vec<u8>) -> Option<Vec<u8>> {
 
-    if buf.len() <= BLOCK_SIZE {
+    if buf.is_empty() {
         return None;
     }
 
     let padding_byte = buf[buf.len() - 1];
-    if padding_byte >= (BLOCK_SIZE as u8) {
+    if padding_byte == 0 || padding_byte > (BLOCK_SIZE as u8) || (padding_byte as usize) > buf.len() {
         return None;
     }
 
@@ -281,6 +446,14 @@ mod tests {
         assert_eq!(expected_with_pad, pad_pkcs(buf_to_pad, 8));
     }
 
+    #[test]
+    fn unpad_pkcs_works() {
+        assert_eq!(Some(b"YELLOW".to_vec()), unpad_pkcs(b"YELLOW\x0a\x0a\x0a\x0a\x0a\x0a\x0a\x0a\x0a\x0a".to_vec()));
+        assert_eq!(Some(b"YELLOW SUBMARINE".to_vec()), unpad_pkcs(pad_pkcs(b"YELLOW SUBMARINE".to_vec(), BLOCK_SIZE)));
+        assert_eq!(None, unpad_pkcs(b"ICE ICE BABY\x01\x02\x03\x04".to_vec()));
+        assert_eq!(None, unpad_pkcs(b"ICE ICE BABY\x05\x05\x05\x00".to_vec()));
+    }
+
     #[test]
     fn aes_ecb_encrypt_works() {
         let to_encrypt = b"YELLOW SUBMARINE".to_vec();
@@ -292,6 +465,14 @@ mod tests {
         assert_eq!(to_encrypt, decrypted);
     }
 
+    #[test]
+    fn aes_ctr_works() {
+        let encrypted = util::hex_to_binary("2fbee76bf9eb16c2afca777a1f33a81bb1874cb5ec4d5bbdaaf63fdacc8b5f384fc1ecb23132542eeffafe45d7d0a4afa0e2d215").unwrap();
+        let decrypted = aes_ctr_crypt(b"YELLOW SUBMARINE", 0, &encrypted).unwrap();
+        assert_eq!(b"Yo, VIP Let's kick it Ice, Ice, baby Ice, Ice, baby ".to_vec(), decrypted);
+        assert_eq!(encrypted, aes_ctr_crypt(b"YELLOW SUBMARINE", 0, &decrypted).unwrap());
+    }
+
     #[test]
     fn aes_cbc_works() {
         let to_encrypt = b"YELLOW SUBMARINE, this string is longer and probably should be padded".to_vec();
@@ -303,4 +484,80 @@ mod tests {
         let decrypted = aes_cbc_decrypt(key, &encrypted, iv, Padding::PKCS7).unwrap();
         assert_eq!(to_encrypt, decrypted);
     }
-}
\ No newline at end of file
+
+    const NIST_KEY: &str = "2b7e151628aed2a6abf7158809cf4f3c";
+    const NIST_IV: &str = "000102030405060708090a0b0c0d0e0f";
+    const NIST_PLAINTEXT: &str = "6bc1bee22e409f96e93d7e117393172aae2d8a571e03ac9c9eb76fac45af8e5130c81c46a35ce411e5fbc1191a0a52eff69f2445df4f9b17ad2b417be66c3710";
+
+    #[test]
+    fn aes_cfb_works() {
+        // NIST SP 800-38A F.3.1, F.3.7 and F.3.13
+        let key = util::hex_to_binary(NIST_KEY).unwrap();
+        let iv = util::hex_to_binary(NIST_IV).unwrap();
+        let plaintext = util::hex_to_binary(NIST_PLAINTEXT).unwrap();
+        let cases = [
+            (1, &plaintext[..2], "68b3"),
+            (8, &plaintext[..18], "3b79424c9c0dd436bace9e0ed4586a4f32b9"),
+            (128, &plaintext[..], "3b3fd92eb72dad20333449f8e83cfb4ac8a64537a0b3a93fcde3cdad9f1ce58b26751f67a3cbb140b1808cf187a4f4dfc04b05357c5d1c0eeac4c66f9ff7f2e6"),
+        ];
+        for &(segment_bits, data, expected) in &cases {
+            let encrypted = aes_cfb_encrypt(&key, &iv, data, segment_bits).unwrap();
+            assert_eq!(expected, util::binary_to_hex(&encrypted));
+            assert_eq!(data.to_vec(), aes_cfb_decrypt(&key, &iv, &encrypted, segment_bits).unwrap());
+        }
+
+        let short = &plaintext[..21];
+        let encrypted = aes_cfb_encrypt(&key, &iv, short, 128).unwrap();
+        assert_eq!(symm::encrypt(symm::Cipher::aes_128_cfb128(), &key, Some(&iv), short).unwrap(), encrypted);
+        let encrypted = aes_cfb_encrypt(&key, &iv, short, 1).unwrap();
+        assert_eq!(symm::encrypt(symm::Cipher::aes_128_cfb1(), &key, Some(&iv), short).unwrap(), encrypted);
+
+        assert_eq!(None, aes_cfb_encrypt(&key, &iv, short, 0));
+        assert_eq!(None, aes_cfb_encrypt(&key, &iv, short, 129));
+        assert_eq!(None, aes_cfb_decrypt(&key, &iv[..8], short, 8));
+    }
+
+    #[test]
+    fn aes_ofb_works() {
+        // NIST SP 800-38A F.4.1
+        let key = util::hex_to_binary(NIST_KEY).unwrap();
+        let iv = util::hex_to_binary(NIST_IV).unwrap();
+        let plaintext = util::hex_to_binary(NIST_PLAINTEXT).unwrap();
+        let encrypted = aes_ofb_crypt(&key, &iv, &plaintext).unwrap();
+        assert_eq!("3b3fd92eb72dad20333449f8e83cfb4a7789508d16918f03f53c52dac54ed8259740051e9c5fecf64344f7a82260edcc304c6528f659c77866a510d9c1d6ae5e", util::binary_to_hex(&encrypted));
+        assert_eq!(plaintext, aes_ofb_crypt(&key, &iv, &encrypted).unwrap());
+        assert_eq!(encrypted[..37].to_vec(), aes_ofb_crypt(&key, &iv, &plaintext[..37]).unwrap());
+        assert_eq!(None, aes_ofb_crypt(&key, &iv[..8], &plaintext));
+    }
+
+    #[test]
+    fn aes_xts_works() {
+        // IEEE 1619 vectors 1 and 2, and 15 to 18 for ciphertext stealing
+        let cases = [
+            ("00000000000000000000000000000000", "00000000000000000000000000000000", 0, "0000000000000000000000000000000000000000000000000000000000000000", "917cf69ebd68b2ec9b9fe9a3eadda692cd43d2f59598ed858c02c2652fbf922e"),
+            ("11111111111111111111111111111111", "22222222222222222222222222222222", 0x3333333333, "4444444444444444444444444444444444444444444444444444444444444444", "c454185e6a16936e39334038acef838bfb186fff7480adc4289382ecd6d394f0"),
+            ("fffefdfcfbfaf9f8f7f6f5f4f3f2f1f0", "bfbebdbcbbbab9b8b7b6b5b4b3b2b1b0", 0x123456789a, "000102030405060708090a0b0c0d0e0f10", "6c1625db4671522d3d7599601de7ca09ed"),
+            ("fffefdfcfbfaf9f8f7f6f5f4f3f2f1f0", "bfbebdbcbbbab9b8b7b6b5b4b3b2b1b0", 0x123456789a, "000102030405060708090a0b0c0d0e0f1011", "d069444b7a7e0cab09e24447d24deb1fedbf"),
+            ("fffefdfcfbfaf9f8f7f6f5f4f3f2f1f0", "bfbebdbcbbbab9b8b7b6b5b4b3b2b1b0", 0x123456789a, "000102030405060708090a0b0c0d0e0f101112", "e5df1351c0544ba1350b3363cd8ef4beedbf9d"),
+            ("fffefdfcfbfaf9f8f7f6f5f4f3f2f1f0", "bfbebdbcbbbab9b8b7b6b5b4b3b2b1b0", 0x123456789a, "000102030405060708090a0b0c0d0e0f10111213", "9d84c813f719aa2c7be3f66171c7c5c2edbf9dac"),
+        ];
+        for &(data_key, tweak_key, data_unit, plaintext, expected) in &cases {
+            let data_key = util::hex_to_binary(data_key).unwrap();
+            let tweak_key = util::hex_to_binary(tweak_key).unwrap();
+            let plaintext = util::hex_to_binary(plaintext).unwrap();
+            let encrypted = aes_xts_encrypt(&data_key, &tweak_key, data_unit, &plaintext).unwrap();
+            assert_eq!(expected, util::binary_to_hex(&encrypted));
+            assert_eq!(plaintext, aes_xts_decrypt(&data_key, &tweak_key, data_unit, &encrypted).unwrap());
+        }
+
+        // a length that needs ciphertext stealing, against OpenSSL
+        let key = util::generate_random_bytes(2 * BLOCK_SIZE);
+        let plaintext = util::generate_random_bytes(75);
+        let mut iv = 7u64.to_le_bytes().to_vec();
+        iv.resize(BLOCK_SIZE, 0);
+        let expected = symm::encrypt(symm::Cipher::aes_128_xts(), &key, Some(&iv), &plaintext).unwrap();
+        assert_eq!(Some(expected.clone()), aes_xts_encrypt(&key[..BLOCK_SIZE], &key[BLOCK_SIZE..], 7, &plaintext));
+        assert_eq!(Some(plaintext.clone()), aes_xts_decrypt(&key[..BLOCK_SIZE], &key[BLOCK_SIZE..], 7, &expected));
+        assert_eq!(None, aes_xts_encrypt(&key[..BLOCK_SIZE], &key[BLOCK_SIZE..], 7, &plaintext[..15]));
+    }
+}
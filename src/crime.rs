@@ -0,0 +1,144 @@
+use miniz_oxide::deflate;
+
+use base64;
+use block;
+use block::{BLOCK_SIZE, Padding};
+use util;
+
+const COMPRESSION_LEVEL: u8 = 6;
+const SESSION_ID_BYTES: usize = 32;
+const COOKIE_PREFIX: &[u8] = b"sessionid=";
+const COOKIE_ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/=\n";
+/// Neither the separator nor the filler bytes appear in the request or in the cookie
+const SEPARATOR: &[u8] = b"~#";
+const FILLER_START: u8 = 0x80;
+const MAX_FILLER_LEN: usize = 64;
+/// Runs of this byte compress to a literal plus a match, shifting the length by a few bits instead of whole bytes
+const SHIFTER: u8 = 0x7f;
+const MAX_SHIFTER_LEN: usize = 16;
+const MAX_COOKIE_LEN: usize = 128;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum OracleCipher {
+    Stream,
+    Cbc,
+}
+
+/// Compresses attacker-chosen request bodies along with a secret session cookie, encrypts them
+/// under a fresh key and only reveals the ciphertext length
+pub struct CompressionOracle {
+    cipher: OracleCipher,
+    session_id: Vec<u8>,
+    queries: usize,
+}
+
+impl CompressionOracle {
+    pub fn new(cipher: OracleCipher) -> CompressionOracle {
+        let session_id = base64::b64_encode(&util::generate_random_bytes(SESSION_ID_BYTES)).into_bytes();
+        CompressionOracle::with_session_id(cipher, &session_id)
+    }
+
+    pub fn with_session_id(cipher: OracleCipher, session_id: &[u8]) -> CompressionOracle {
+        CompressionOracle { cipher, session_id: session_id.to_vec(), queries: 0 }
+    }
+
+    pub fn queries(&self) -> usize {
+        self.queries
+    }
+
+    fn format_request(&self, body: &[u8]) -> Vec<u8> {
+        let mut request = b"POST / HTTP/1.1\nHost: hapless.com\nCookie: sessionid=".to_vec();
+        request.extend_from_slice(&self.session_id);
+        request.extend_from_slice(format!("\nContent-Length: {}\n", body.len()).as_bytes());
+        request.extend_from_slice(body);
+        request
+    }
+
+    pub fn encrypted_length(&mut self, body: &[u8]) -> usize {
+        self.queries += 1;
+        let compressed = deflate::compress_to_vec(&self.format_request(body), COMPRESSION_LEVEL);
+        let key = util::generate_random_bytes(BLOCK_SIZE);
+        let encrypted = match self.cipher {
+            OracleCipher::Stream => block::aes_ctr_crypt(&key, 0, &compressed),
+            OracleCipher::Cbc => {
+                let iv = util::generate_random_bytes(BLOCK_SIZE);
+                block::aes_cbc_encrypt(&key, &compressed, &iv, Padding::PKCS7)
+            }
+        };
+        encrypted.unwrap().len()
+    }
+}
+
+fn guess_body(filler: &[u8], known: &[u8], guess: u8, guess_first: bool) -> Vec<u8> {
+    let mut body = filler.to_vec();
+    body.extend_from_slice(COOKIE_PREFIX);
+    body.extend_from_slice(known);
+    if guess_first {
+        body.push(guess);
+        body.extend_from_slice(SEPARATOR);
+    } else {
+        body.extend_from_slice(SEPARATOR);
+        body.push(guess);
+    }
+    body
+}
+
+/// "Two tries": `known` + c + separator and `known` + separator + c contain the same literals, so only a
+/// correct c, which extends the match against the cookie, can make them compress differently (usually
+/// better, but a longer match can also cost more bits when its length code is rarer)
+fn two_tries_winners(oracle: &mut CompressionOracle, filler: &[u8], known: &[u8]) -> Vec<u8> {
+    COOKIE_ALPHABET.iter()
+        .cloned()
+        .filter(|&c| {
+            let extended = oracle.encrypted_length(&guess_body(filler, known, c, true));
+            let separated = oracle.encrypted_length(&guess_body(filler, known, c, false));
+            extended != separated
+        })
+        .collect()
+}
+
+/// CRIME: recovers the session cookie one character at a time. The saving from a correct guess is a few
+/// bits, so incompressible filler (and a run of shifter bytes for the sub-byte offset) is prepended until
+/// it pushes the ciphertext length over a byte (stream cipher) or block (CBC) boundary for exactly one
+/// candidate.
+pub fn recover_session_id(oracle: &mut CompressionOracle) -> Option<Vec<u8>> {
+    let filler: Vec<u8> = (0..MAX_FILLER_LEN).map(|i| FILLER_START + i as u8).collect();
+    let mut known = Vec::new();
+
+    while known.len() < MAX_COOKIE_LEN {
+        let next = (0..MAX_SHIFTER_LEN)
+            .flat_map(|shifter_len| (0..MAX_FILLER_LEN).map(move |filler_len| (shifter_len, filler_len)))
+            .map(|(shifter_len, filler_len)| {
+                let mut padding = vec![SHIFTER; shifter_len];
+                padding.extend_from_slice(&filler[..filler_len]);
+                two_tries_winners(oracle, &padding, &known)
+            })
+            .find(|winners| winners.len() == 1)?[0];
+        if next == b'\n' {
+            return Some(known);
+        }
+        known.push(next);
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn recover_session_id_stream_works() {
+        let mut oracle = CompressionOracle::new(OracleCipher::Stream);
+        let session_id = oracle.session_id.clone();
+        assert_eq!(Some(session_id), recover_session_id(&mut oracle));
+    }
+
+    #[test]
+    fn recover_session_id_cbc_works() {
+        let mut oracle = CompressionOracle::new(OracleCipher::Cbc);
+        let session_id = oracle.session_id.clone();
+        assert_eq!(Some(session_id), recover_session_id(&mut oracle));
+        assert!(oracle.queries() > 0);
+    }
+}
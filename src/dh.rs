@@ -0,0 +1,102 @@
+use num_bigint::BigUint;
+use openssl::sha;
+
+use block;
+use util;
+
+const NIST_P_HEX: &str = "ffffffffffffffffc90fdaa22168c234c4c6628b80dc1cd129024e088a67cc74020bbea63b139b22514a08798e3404ddef9519b3cd3a431b302b0a6df25f14374fe1356d6d51c245e485b576625e7ec6f44c42e9a637ed6b0bff5cb6f406b7edee386bfb5a899fa5ae9f24117c4b1fe649286651ece45b3dc2007cb8a163bf0598da48361c55d39a69163fa8fd24cf5f83655d23dca3ad961c62f356208552bb9ed529077096966d670c354e4abc9804f1746c08ca237327ffffffffffffffff";
+const NIST_G: u32 = 2;
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct DhParams {
+    pub p: BigUint,
+    pub g: BigUint,
+}
+
+impl DhParams {
+    pub fn new(p: BigUint, g: BigUint) -> DhParams {
+        DhParams { p, g }
+    }
+
+    pub fn nist() -> DhParams {
+        let p = BigUint::parse_bytes(NIST_P_HEX.as_bytes(), 16).unwrap();
+        DhParams::new(p, BigUint::from(NIST_G))
+    }
+
+    pub fn generate_keypair(&self) -> DhKeyPair {
+        let private = util::generate_random_biguint_below(&self.p);
+        let public = self.g.modpow(&private, &self.p);
+        DhKeyPair { private, public }
+    }
+
+    /// Key pair with the private key below `q`, the order of `g`
+    pub fn generate_subgroup_keypair(&self, q: &BigUint) -> DhKeyPair {
+        let private = util::generate_random_biguint_below(q);
+        let public = self.g.modpow(&private, &self.p);
+        DhKeyPair { private, public }
+    }
+
+    pub fn shared_secret(&self, keypair: &DhKeyPair, other_public: &BigUint) -> BigUint {
+        other_public.modpow(&keypair.private, &self.p)
+    }
+}
+
+pub struct DhKeyPair {
+    private: BigUint,
+    pub public: BigUint,
+}
+
+/// Derives a 128-bit AES key from a shared secret as SHA1(s)[0..16].
+pub fn derive_aes_key(shared_secret: &BigUint) -> Vec<u8> {
+    sha::sha1(&shared_secret.to_bytes_be())[..block::BLOCK_SIZE].to_vec()
+}
+
+/// Encrypts `msg` under the key derived from `shared_secret`, the random IV is appended to the ciphertext.
+pub fn encrypt_message(shared_secret: &BigUint, msg: &[u8]) -> Vec<u8> {
+    let key = derive_aes_key(shared_secret);
+    let iv = util::generate_random_bytes(block::BLOCK_SIZE);
+    let mut encrypted = block::aes_cbc_encrypt(&key, msg, &iv, block::Padding::PKCS7).unwrap();
+    encrypted.extend_from_slice(&iv);
+    encrypted
+}
+
+pub fn decrypt_message(shared_secret: &BigUint, data: &[u8]) -> Option<Vec<u8>> {
+    if data.len() < 2 * block::BLOCK_SIZE || !data.len().is_multiple_of(block::BLOCK_SIZE) {
+        return None;
+    }
+    let key = derive_aes_key(shared_secret);
+    let (cipher_text, iv) = data.split_at(data.len() - block::BLOCK_SIZE);
+    block::aes_cbc_decrypt(&key, cipher_text, iv, block::Padding::PKCS7)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shared_secret_agrees() {
+        let params = DhParams::nist();
+        let alice = params.generate_keypair();
+        let bob = params.generate_keypair();
+        assert_eq!(params.shared_secret(&alice, &bob.public), params.shared_secret(&bob, &alice.public));
+    }
+
+    #[test]
+    fn small_group_shared_secret_agrees() {
+        let params = DhParams::new(BigUint::from(37u32), BigUint::from(5u32));
+        let alice = params.generate_keypair();
+        let bob = params.generate_keypair();
+        assert_eq!(params.shared_secret(&alice, &bob.public), params.shared_secret(&bob, &alice.public));
+    }
+
+    #[test]
+    fn message_encryption_roundtrip() {
+        let secret = BigUint::from(12345u32);
+        for &msg in &[&b"Hello, Bob! This is a message from Alice"[..], b"hi", b"YELLOW SUBMARINE", b""] {
+            let encrypted = encrypt_message(&secret, msg);
+            assert_eq!(Some(msg.to_vec()), decrypt_message(&secret, &encrypted));
+        }
+        let encrypted = encrypt_message(&secret, b"hi");
+        assert_eq!(None, decrypt_message(&secret, &encrypted[1..]));
+    }
+}
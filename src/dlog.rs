@@ -0,0 +1,244 @@
+use std::collections::HashMap;
+use std::hash::Hash;
+
+use num_bigint::BigUint;
+use num_integer::Integer;
+use num_traits::{One, ToPrimitive, Zero};
+
+use numtheory;
+
+/// Just enough of a cyclic group for generic discrete log algorithms
+pub trait Group {
+    type Element: Clone + Eq + Hash;
+
+    fn identity(&self) -> Self::Element;
+    fn combine(&self, a: &Self::Element, b: &Self::Element) -> Self::Element;
+    fn invert(&self, a: &Self::Element) -> Self::Element;
+    fn exp(&self, a: &Self::Element, n: &BigUint) -> Self::Element;
+    /// Deterministic pseudo-random value derived from an element, used to pick kangaroo jumps
+    fn jump_index(&self, a: &Self::Element, k: u32) -> u32;
+}
+
+/// Multiplicative group of integers mod p
+pub struct ModP<'a> {
+    pub p: &'a BigUint,
+}
+
+impl<'a> Group for ModP<'a> {
+    type Element = BigUint;
+
+    fn identity(&self) -> BigUint {
+        BigUint::one()
+    }
+
+    fn combine(&self, a: &BigUint, b: &BigUint) -> BigUint {
+        (a * b) % self.p
+    }
+
+    fn invert(&self, a: &BigUint) -> BigUint {
+        numtheory::invmod(a, self.p).unwrap()
+    }
+
+    fn exp(&self, a: &BigUint, n: &BigUint) -> BigUint {
+        a.modpow(n, self.p)
+    }
+
+    fn jump_index(&self, a: &BigUint, k: u32) -> u32 {
+        (a % k).to_u32().unwrap()
+    }
+}
+
+/// Benchmark counters: group operations in the walks/steps and the number of precomputed table entries
+#[derive(Clone, Copy, Default, Debug, PartialEq)]
+pub struct DlogStats {
+    pub iterations: u64,
+    pub table_size: usize,
+}
+
+/// Jump function f(y) = 2^(y mod k) and tame kangaroo walk length n
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct KangarooConfig {
+    pub k: u32,
+    pub n: u64,
+}
+
+impl KangarooConfig {
+    /// The smallest k with a mean jump of at least sqrt(b - a) / 2, and a walk of 4 mean jumps
+    pub fn for_interval(a: &BigUint, b: &BigUint) -> KangarooConfig {
+        let target = (b - a).sqrt() / 2u32;
+        let mut k = 1;
+        while mean_jump(k) < target {
+            k += 1;
+        }
+        let n = (mean_jump(k) * 4u32).to_u64().unwrap_or(u64::MAX);
+        KangarooConfig { k, n }
+    }
+}
+
+fn mean_jump(k: u32) -> BigUint {
+    ((BigUint::one() << k) - 1u32) / k
+}
+
+/// Pollard's kangaroo: x in [a, b] with g^x = y mod p, in about sqrt(b - a) multiplications. The walk is
+/// probabilistic, `None` means the wild kangaroo overshot and a different config may work.
+pub fn kangaroo(g: &BigUint, y: &BigUint, p: &BigUint, a: &BigUint, b: &BigUint) -> Option<BigUint> {
+    let config = KangarooConfig::for_interval(a, b);
+    kangaroo_in(&ModP { p }, g, y, a, b, &config, &mut DlogStats::default())
+}
+
+pub fn kangaroo_in<G: Group>(group: &G, g: &G::Element, y: &G::Element, a: &BigUint, b: &BigUint, config: &KangarooConfig, stats: &mut DlogStats) -> Option<BigUint> {
+    let jumps: Vec<G::Element> = (0..config.k)
+        .map(|i| group.exp(g, &(BigUint::one() << i)))
+        .collect();
+    stats.table_size = jumps.len();
+
+    let mut tame_distance = BigUint::zero();
+    let mut tame = group.exp(g, b);
+    for _ in 0..config.n {
+        let i = group.jump_index(&tame, config.k);
+        tame_distance += BigUint::one() << i;
+        tame = group.combine(&tame, &jumps[i as usize]);
+        stats.iterations += 1;
+    }
+
+    let limit = b - a + &tame_distance;
+    let mut wild_distance = BigUint::zero();
+    let mut wild = y.clone();
+    while wild_distance <= limit {
+        if wild == tame {
+            return Some(b + tame_distance - wild_distance);
+        }
+        let i = group.jump_index(&wild, config.k);
+        wild_distance += BigUint::one() << i;
+        wild = group.combine(&wild, &jumps[i as usize]);
+        stats.iterations += 1;
+    }
+
+    None
+}
+
+/// Baby-step giant-step: x in [a, b] with g^x = y mod p, deterministic but with a sqrt(b - a) entry table
+pub fn baby_step_giant_step(g: &BigUint, y: &BigUint, p: &BigUint, a: &BigUint, b: &BigUint) -> Option<BigUint> {
+    baby_step_giant_step_in(&ModP { p }, g, y, a, b, &mut DlogStats::default())
+}
+
+pub fn baby_step_giant_step_in<G: Group>(group: &G, g: &G::Element, y: &G::Element, a: &BigUint, b: &BigUint, stats: &mut DlogStats) -> Option<BigUint> {
+    let m = (b - a + 1u32).sqrt() + 1u32;
+    let m_u64 = m.to_u64()?;
+
+    let mut baby_steps: HashMap<G::Element, u64> = HashMap::new();
+    let mut current = group.identity();
+    for j in 0..m_u64 {
+        baby_steps.entry(current.clone()).or_insert(j);
+        current = group.combine(&current, g);
+        stats.iterations += 1;
+    }
+    stats.table_size = baby_steps.len();
+
+    // y * g^-a = g^(i * m + j)
+    let giant_step = group.invert(&group.exp(g, &m));
+    let mut current = group.combine(y, &group.invert(&group.exp(g, a)));
+    for i in 0..=m_u64 {
+        if let Some(&j) = baby_steps.get(&current) {
+            let x = a + &m * i + j;
+            return if x <= *b { Some(x) } else { None };
+        }
+        current = group.combine(&current, &giant_step);
+        stats.iterations += 1;
+    }
+
+    None
+}
+
+const KANGAROO_ATTEMPTS: usize = 3;
+
+/// Finishes a partially known exponent: given x = n mod r and x in [0, q), finds x = n + m * r with a
+/// kangaroo walk over m in [0, (q - 1) / r] on y' = y * g^-n and g' = g^r. A failed walk is retried with
+/// a longer tame kangaroo.
+pub fn complete_partial_key<G: Group>(group: &G, g: &G::Element, y: &G::Element, q: &BigUint, n: &BigUint, r: &BigUint, stats: &mut DlogStats) -> Option<BigUint> {
+    let n = n.mod_floor(r);
+    let y_prime = group.combine(y, &group.invert(&group.exp(g, &n)));
+    let g_prime = group.exp(g, r);
+    let upper = (q - 1u32) / r;
+    let mut config = KangarooConfig::for_interval(&BigUint::zero(), &upper);
+    for _ in 0..KANGAROO_ATTEMPTS {
+        if let Some(m) = kangaroo_in(group, &g_prime, &y_prime, &BigUint::zero(), &upper, &config, stats) {
+            return Some(n + m * r);
+        }
+        config.n *= 2;
+    }
+    None
+}
+
+/// Pohlig-Hellman for a group of square-free order, the product of the primes in `factors`: x mod r in the
+/// subgroup of every prime order r with baby-step giant-step, combined with CRT. `None` if y isn't a power of g.
+pub fn pohlig_hellman<G: Group>(group: &G, g: &G::Element, y: &G::Element, factors: &[u64], stats: &mut DlogStats) -> Option<BigUint> {
+    let order: BigUint = factors.iter().map(|&r| BigUint::from(r)).product();
+    let mut residues = Vec::new();
+    let mut moduli = Vec::new();
+    for &r in factors {
+        let r = BigUint::from(r);
+        let cofactor = &order / &r;
+        let g_r = group.exp(g, &cofactor);
+        let y_r = group.exp(y, &cofactor);
+        residues.push(baby_step_giant_step_in(group, &g_r, &y_r, &BigUint::zero(), &(&r - 1u32), stats)?);
+        moduli.push(r);
+    }
+    numtheory::crt(&residues, &moduli).map(|(x, _)| x)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use util;
+
+    const P_DEC: &str = "11470374874925275658116663507232161402086650258453896274534991676898999262641581519101074740642369848233294239851519212341844337347119899874391456329785623";
+    const G_DEC: &str = "622952335333961296978159266084741085889881358738459939978290179936063635566740258555167783009058567397963466103140082647486611657350811560630587013183357";
+
+    fn params() -> (BigUint, BigUint) {
+        (BigUint::parse_bytes(P_DEC.as_bytes(), 10).unwrap(), BigUint::parse_bytes(G_DEC.as_bytes(), 10).unwrap())
+    }
+
+    #[test]
+    fn kangaroo_works() {
+        let (p, g) = params();
+        let y = BigUint::parse_bytes(b"7760073848032689505395005705677365876654629189298052775754597607446617558600394076764814236081991643094239886772481052254010323780165093955236429914607119", 10).unwrap();
+        let b = BigUint::from(1u32) << 20;
+        assert_eq!(Some(BigUint::from(705485u32)), kangaroo(&g, &y, &p, &BigUint::zero(), &b));
+    }
+
+    #[test]
+    fn baby_step_giant_step_works() {
+        let (p, g) = params();
+        let x = BigUint::from(1_234_567u32);
+        let y = g.modpow(&x, &p);
+        let a = BigUint::from(1_000_000u32);
+        let b = BigUint::from(2_000_000u32);
+        let mut stats = DlogStats::default();
+        assert_eq!(Some(x), baby_step_giant_step_in(&ModP { p: &p }, &g, &y, &a, &b, &mut stats));
+        assert_eq!(1001, stats.table_size);
+    }
+
+    #[test]
+    fn complete_partial_key_works() {
+        let (p, g) = params();
+        let q = BigUint::from(1u64 << 40);
+        let x = BigUint::from(987_654_321_987u64);
+        let y = g.modpow(&x, &p);
+        let r = BigUint::from(1_000_003u32);
+        let mut stats = DlogStats::default();
+        let recovered = complete_partial_key(&ModP { p: &p }, &g, &y, &q, &(&x % &r), &r, &mut stats);
+        assert_eq!(Some(x), recovered);
+        assert!(stats.iterations > 0);
+    }
+
+    #[test]
+    fn pohlig_hellman_works() {
+        let (p, factors) = numtheory::generate_smooth_prime(256, 1 << 16, &[]);
+        let g = util::generate_random_biguint_below(&p);
+        let x = util::generate_random_biguint_below(&p);
+        let y = g.modpow(&x, &p);
+        let found = pohlig_hellman(&ModP { p: &p }, &g, &y, &factors, &mut DlogStats::default()).unwrap();
+        assert_eq!(y, g.modpow(&found, &p));
+    }
+}
@@ -0,0 +1,279 @@
+use num_bigint::BigUint;
+use num_traits::{One, Zero};
+use openssl::sha;
+
+use numtheory;
+use util;
+
+const DEFAULT_P_HEX: &str = "800000000000000089e1855218a0e7dac38136ffafa72eda7859f2171e25e65eac698c1702578b07dc2a1076da241c76c62d374d8389ea5aeffd3226a0530cc565f3bf6b50929139ebeac04f48c3c84afb796d61e5a4f9a8fda812ab59494232c7d2b4deb50aa18ee9e132bfa85ac4374d7f9091abc3d015efc871a584471bb1";
+const DEFAULT_Q_HEX: &str = "f4f47f05794b256174bba6e9b396a7707e563c5b";
+const DEFAULT_G_HEX: &str = "5958c9d3898b224b12672c0b98e06c60df923cb8bc999d119458fef538b8fa4046c8db53039db620c094c9fa077ef389b5322a559946a71903f990f1f7e0e025e2d7f7cf494aff1a0470f5b64c36b625a097f1651fe775323556fe00b3608c887892878480e99041be601a62166ca6894bdd41a7054ec89f756ba9fc95302291";
+
+const SHA1_BITS: u64 = 160;
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct DsaParams {
+    pub p: BigUint,
+    pub q: BigUint,
+    pub g: BigUint,
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct DsaSignature {
+    pub r: BigUint,
+    pub s: BigUint,
+}
+
+pub struct DsaKeyPair {
+    x: BigUint,
+    pub y: BigUint,
+}
+
+fn parse_hex(hex_str: &str) -> BigUint {
+    BigUint::parse_bytes(hex_str.as_bytes(), 16).unwrap()
+}
+
+impl DsaParams {
+    /// 1024-bit p and 160-bit q parameters used by the cryptopals DSA challenges
+    pub fn default_params() -> DsaParams {
+        DsaParams { p: parse_hex(DEFAULT_P_HEX), q: parse_hex(DEFAULT_Q_HEX), g: parse_hex(DEFAULT_G_HEX) }
+    }
+
+    /// Generates p of `l_bits` bits and q of `n_bits` bits with q | p - 1, and g of order q
+    pub fn generate(l_bits: usize, n_bits: usize) -> DsaParams {
+        assert!(l_bits > n_bits);
+        let q = numtheory::generate_prime(n_bits);
+        let two_q = &q * 2u32;
+        let one = BigUint::one();
+
+        let p = loop {
+            let x = numtheory::random_odd_with_bits(l_bits);
+            let p = &x - (&x % &two_q) + &one;
+            if p.bits() as usize == l_bits && numtheory::is_probable_prime(&p) {
+                break p;
+            }
+        };
+
+        let cofactor = (&p - &one) / &q;
+        let two = BigUint::from(2u32);
+        let g = loop {
+            let h = util::generate_random_biguint_below(&(&p - 3u32)) + &two;
+            let g = h.modpow(&cofactor, &p);
+            if g != one {
+                break g;
+            }
+        };
+
+        DsaParams { p, q, g }
+    }
+
+    pub fn with_generator(&self, g: BigUint) -> DsaParams {
+        DsaParams { p: self.p.clone(), q: self.q.clone(), g }
+    }
+
+    pub fn generate_keypair(&self) -> DsaKeyPair {
+        let x = util::generate_random_biguint_below(&(&self.q - 1u32)) + 1u32;
+        let y = self.public_key(&x);
+        DsaKeyPair { x, y }
+    }
+
+    pub fn public_key(&self, x: &BigUint) -> BigUint {
+        self.g.modpow(x, &self.p)
+    }
+
+    /// SHA-1 of the message, truncated to the bit length of q
+    pub fn hash_message(&self, msg: &[u8]) -> BigUint {
+        let h = BigUint::from_bytes_be(&sha::sha1(msg));
+        let q_bits = self.q.bits();
+        if q_bits < SHA1_BITS {
+            h >> (SHA1_BITS - q_bits)
+        } else {
+            h
+        }
+    }
+
+    pub fn sign(&self, keypair: &DsaKeyPair, msg: &[u8]) -> DsaSignature {
+        loop {
+            let k = util::generate_random_biguint_below(&(&self.q - 1u32)) + 1u32;
+            let signature = self.sign_with_nonce(&keypair.x, &k, msg);
+            if !signature.r.is_zero() && !signature.s.is_zero() {
+                return signature;
+            }
+        }
+    }
+
+    /// Signs with the given nonce and without rejecting r = 0 or s = 0, which lets a tampered g be observed
+    pub fn sign_with_nonce(&self, x: &BigUint, k: &BigUint, msg: &[u8]) -> DsaSignature {
+        let r = self.g.modpow(k, &self.p) % &self.q;
+        let k_inv = numtheory::invmod(k, &self.q).unwrap();
+        let s = (k_inv * (self.hash_message(msg) + x * &r)) % &self.q;
+        DsaSignature { r, s }
+    }
+
+    pub fn verify(&self, y: &BigUint, msg: &[u8], signature: &DsaSignature) -> bool {
+        if signature.r.is_zero() || signature.r >= self.q || signature.s.is_zero() || signature.s >= self.q {
+            return false;
+        }
+        self.verify_unchecked(y, msg, signature)
+    }
+
+    /// Verification without the 0 < r < q and 0 < s < q range checks
+    pub fn verify_unchecked(&self, y: &BigUint, msg: &[u8], signature: &DsaSignature) -> bool {
+        let w = match numtheory::invmod(&signature.s, &self.q) {
+            Some(w) => w,
+            None => return false,
+        };
+        let u1 = (self.hash_message(msg) * &w) % &self.q;
+        let u2 = (&signature.r * &w) % &self.q;
+        let v = ((self.g.modpow(&u1, &self.p) * y.modpow(&u2, &self.p)) % &self.p) % &self.q;
+        v == signature.r
+    }
+}
+
+/// x = (s * k - H(m)) / r mod q
+pub fn recover_x_from_nonce(params: &DsaParams, msg: &[u8], signature: &DsaSignature, k: &BigUint) -> Option<BigUint> {
+    let q = &params.q;
+    let r_inv = numtheory::invmod(&signature.r, q)?;
+    let sk = (&signature.s * k) % q;
+    let h = params.hash_message(msg) % q;
+    Some(((sk + q - h) * r_inv) % q)
+}
+
+/// Recovers x when the nonce is known to be below `max_k`, returns (k, x)
+pub fn brute_force_nonce(
+    params: &DsaParams,
+    y: &BigUint,
+    msg: &[u8],
+    signature: &DsaSignature,
+    max_k: u64,
+) -> Option<(BigUint, BigUint)> {
+    let mut g_k = BigUint::one();
+    for k in 0..max_k {
+        if &g_k % &params.q == signature.r {
+            let k = BigUint::from(k);
+            if let Some(x) = recover_x_from_nonce(params, msg, signature, &k) {
+                if params.public_key(&x) == *y {
+                    return Some((k, x));
+                }
+            }
+        }
+        g_k = (g_k * &params.g) % &params.p;
+    }
+    None
+}
+
+/// Finds two signatures sharing r (and so k) and recovers x from them:
+/// k = (H(m1) - H(m2)) / (s1 - s2) mod q
+pub fn recover_x_from_repeated_nonce(params: &DsaParams, y: &BigUint, signed: &[(Vec<u8>, DsaSignature)]) -> Option<BigUint> {
+    let q = &params.q;
+    for i in 0..signed.len() {
+        for j in (i + 1)..signed.len() {
+            let (ref msg1, ref sig1) = signed[i];
+            let (ref msg2, ref sig2) = signed[j];
+            if sig1.r != sig2.r || sig1.s == sig2.s {
+                continue;
+            }
+
+            let h_diff = (params.hash_message(msg1) % q + q - params.hash_message(msg2) % q) % q;
+            let s_diff = (&sig1.s + q - &sig2.s) % q;
+            let k = match numtheory::invmod(&s_diff, q) {
+                Some(s_diff_inv) => (h_diff * s_diff_inv) % q,
+                None => continue,
+            };
+            if let Some(x) = recover_x_from_nonce(params, msg1, sig1, &k) {
+                if params.public_key(&x) == *y {
+                    return Some(x);
+                }
+            }
+        }
+    }
+    None
+}
+
+/// With g = 0 every signature has r = 0, and a verifier without range checks accepts it for any message
+pub fn magic_signature_zero_generator() -> DsaSignature {
+    DsaSignature { r: BigUint::zero(), s: BigUint::one() }
+}
+
+/// With g = p + 1 the signature r = (y^z mod p) mod q, s = r / z mod q verifies for any message
+pub fn magic_signature_p_plus_one(params: &DsaParams, y: &BigUint, z: &BigUint) -> Option<DsaSignature> {
+    let r = y.modpow(z, &params.p) % &params.q;
+    let s = (&r * numtheory::invmod(z, &params.q)?) % &params.q;
+    Some(DsaSignature { r, s })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dsa_sign_and_verify_work() {
+        let params = DsaParams::default_params();
+        let keypair = params.generate_keypair();
+        let signature = params.sign(&keypair, b"sign me");
+        assert!(params.verify(&keypair.y, b"sign me", &signature));
+        assert!(!params.verify(&keypair.y, b"sign you", &signature));
+    }
+
+    #[test]
+    fn dsa_generated_params_work() {
+        let params = DsaParams::generate(512, 160);
+        assert_eq!(512, params.p.bits());
+        assert!(((&params.p - 1u32) % &params.q).is_zero());
+        assert!(params.g.modpow(&params.q, &params.p).is_one());
+
+        let keypair = params.generate_keypair();
+        let signature = params.sign(&keypair, b"sign me");
+        assert!(params.verify(&keypair.y, b"sign me", &signature));
+    }
+
+    #[test]
+    fn brute_force_nonce_works() {
+        let params = DsaParams::default_params();
+        let y = parse_hex("84ad4719d044495496a3201c8ff484feb45b962e7302e56a392aee4abab3e4bdebf2955b4736012f21a08084056b19bcd7fee56048e004e44984e2f411788efdc837a0d2e5abb7b555039fd243ac01f0fb2ed1dec568280ce678e931868d23eb095fde9d3779191b8c0299d6e07bbb283e6633451e535c45513b2d33c99ea17");
+        let msg = b"For those that envy a MC it can be hazardous to your health\nSo be friendly, a matter of life and death, just like a etch-a-sketch\n";
+        let signature = DsaSignature {
+            r: BigUint::parse_bytes(b"548099063082341131477253921760299949438196259240", 10).unwrap(),
+            s: BigUint::parse_bytes(b"857042759984254168557880549501802188789837994940", 10).unwrap(),
+        };
+        assert_eq!(parse_hex("d2d0714f014a9784047eaeccf956520045c45265"), params.hash_message(msg));
+
+        let (_, x) = brute_force_nonce(&params, &y, msg, &signature, 1 << 16).unwrap();
+        let x_hex = util::binary_to_hex(&x.to_bytes_be());
+        assert_eq!(util::hex_to_binary("0954edd5e0afe5542a4adf012611a91912a3ec16").unwrap(),
+                    sha::sha1(x_hex.as_bytes()).to_vec());
+    }
+
+    #[test]
+    fn recover_x_from_repeated_nonce_works() {
+        let params = DsaParams::default_params();
+        let keypair = params.generate_keypair();
+        let k = util::generate_random_biguint_below(&params.q);
+        let signed = vec![
+            (b"first message".to_vec(), params.sign(&keypair, b"first message")),
+            (b"second message".to_vec(), params.sign_with_nonce(&keypair.x, &k, b"second message")),
+            (b"third message".to_vec(), params.sign_with_nonce(&keypair.x, &k, b"third message")),
+        ];
+        assert_eq!(Some(keypair.x.clone()), recover_x_from_repeated_nonce(&params, &keypair.y, &signed));
+        assert_eq!(None, recover_x_from_repeated_nonce(&params, &keypair.y, &signed[..2]));
+    }
+
+    #[test]
+    fn magic_signatures_work() {
+        let params = DsaParams::default_params();
+        let keypair = params.generate_keypair();
+
+        let zero_params = params.with_generator(BigUint::zero());
+        let k = util::generate_random_biguint_below(&params.q);
+        assert!(zero_params.sign_with_nonce(&keypair.x, &k, b"Hello, world").r.is_zero());
+        let signature = magic_signature_zero_generator();
+        assert!(zero_params.verify_unchecked(&keypair.y, b"Hello, world", &signature));
+        assert!(zero_params.verify_unchecked(&keypair.y, b"Goodbye, world", &signature));
+        assert!(!zero_params.verify(&keypair.y, b"Goodbye, world", &signature));
+
+        let tampered_params = params.with_generator(&params.p + 1u32);
+        let signature = magic_signature_p_plus_one(&params, &keypair.y, &BigUint::from(42u32)).unwrap();
+        assert!(tampered_params.verify(&keypair.y, b"Hello, world", &signature));
+        assert!(tampered_params.verify(&keypair.y, b"Goodbye, world", &signature));
+    }
+}
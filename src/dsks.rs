@@ -0,0 +1,131 @@
+use num_bigint::BigUint;
+use num_traits::{One, Zero};
+
+use dlog;
+use dlog::{DlogStats, ModP};
+use ec::{Curve, EcKeyPair, Point};
+use ecdsa;
+use ecdsa::EcdsaSignature;
+use numtheory;
+use rsa;
+use rsa::{RsaKeyPair, RsaPublicKey};
+use util;
+
+/// Small factors of p - 1 and q - 1 stay below this, so every discrete log is a quick baby-step giant-step
+pub const SMOOTH_FACTOR_BOUND: u64 = 1 << 16;
+
+/// Whether g generates the whole multiplicative group mod p, given the prime factors of p - 1
+fn is_primitive_root(g: &BigUint, p: &BigUint, factors: &[u64]) -> bool {
+    let p_minus_one = p - 1u32;
+    factors.iter().all(|&r| !g.modpow(&(&p_minus_one / r), p).is_one())
+}
+
+/// Smooth prime of `bits` bits or more for which `s` is a primitive root, so s^e = m has a solution for any m
+fn smooth_prime_for(s: &BigUint, bits: usize, avoid: &[u64]) -> (BigUint, Vec<u64>) {
+    loop {
+        let (p, factors) = numtheory::generate_smooth_prime(bits, SMOOTH_FACTOR_BOUND, avoid);
+        if is_primitive_root(&(s % &p), &p, &factors) {
+            return (p, factors);
+        }
+    }
+}
+
+/// Duplicate-signature key selection against RSA: a fresh key pair (e', N') under which the existing
+/// `signature` on the message representative `m` verifies too. p - 1 and q - 1 are smooth and share only
+/// the factor 2, so e' = log_s(m) is found mod p - 1 and mod q - 1 with Pohlig-Hellman and joined with CRT.
+pub fn rsa_dsks(public: &RsaPublicKey, m: &BigUint, signature: &BigUint) -> RsaKeyPair {
+    let bits = public.n.bits() as usize / 2 + 1;
+    loop {
+        let (p, p_factors) = smooth_prime_for(signature, bits, &[]);
+        let (q, q_factors) = smooth_prime_for(signature, bits, &p_factors[1..]);
+
+        let mut stats = DlogStats::default();
+        let e_p = dlog::pohlig_hellman(&ModP { p: &p }, &(signature % &p), &(m % &p), &p_factors, &mut stats);
+        let e_q = dlog::pohlig_hellman(&ModP { p: &q }, &(signature % &q), &(m % &q), &q_factors, &mut stats);
+        let (e_p, e_q) = match (e_p, e_q) {
+            (Some(e_p), Some(e_q)) => (e_p, e_q),
+            _ => continue,
+        };
+        // both logs are taken mod an even number, they have to agree on the shared factor 2
+        if e_p.bit(0) != e_q.bit(0) {
+            continue;
+        }
+
+        let mut residues = Vec::new();
+        let mut moduli = Vec::new();
+        for &r in &p_factors {
+            residues.push(&e_p % r);
+            moduli.push(BigUint::from(r));
+        }
+        for &r in &q_factors[1..] {
+            residues.push(&e_q % r);
+            moduli.push(BigUint::from(r));
+        }
+        let e = match numtheory::crt(&residues, &moduli) {
+            Some((e, _)) => e,
+            None => continue,
+        };
+        if let Some(keypair) = rsa::keypair_from_primes(p, q, &e) {
+            return keypair;
+        }
+    }
+}
+
+/// The ECDSA version: the curve with a new generator G' and a key pair on it for which `signature` on `msg`
+/// verifies. With u1 = H(m) / s and u2 = r / s, any d' gives t = u1 + u2 d', G' = (u1 G + u2 Q) / t and
+/// Q' = d' G', since u1 G' + u2 Q' = t G' = R.
+pub fn ecdsa_dsks(curve: &Curve, public: &Point, msg: &[u8], signature: &EcdsaSignature) -> Option<(Curve, EcKeyPair)> {
+    let n = &curve.n;
+    let w = numtheory::invmod(&signature.s, n)?;
+    let u1 = ecdsa::hash_message(curve, msg) * &w % n;
+    let u2 = &signature.r * w % n;
+    let big_r = curve.add(&curve.scalar_mul(&curve.g, &u1), &curve.scalar_mul(public, &u2));
+
+    loop {
+        let d = util::generate_random_biguint_below(&(n - 1u32)) + 1u32;
+        let t = (&u1 + &u2 * &d) % n;
+        if t.is_zero() {
+            continue;
+        }
+        let g = curve.scalar_mul(&big_r, &numtheory::invmod(&t, n)?);
+        let forged = Curve { g, ..curve.clone() };
+        let keypair = forged.keypair_from_private(d);
+        return Some((forged, keypair));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pkcs1;
+    use pkcs1::HashAlgorithm;
+
+    #[test]
+    fn rsa_dsks_works() {
+        let original = rsa::generate_keypair(512, rsa::DEFAULT_E);
+        let msg = b"Transfer $100 to Bob";
+        let block = pkcs1::pad_signature_block(HashAlgorithm::Sha256, msg, original.public.size_in_bytes());
+        let m = rsa::bytes_to_int(&block);
+        let signature = original.private.sign(&m);
+        assert!(original.public.verify(&m, &signature));
+
+        let forged = rsa_dsks(&original.public, &m, &signature);
+        assert_ne!(original.public, forged.public);
+        assert!(forged.public.verify(&m, &signature));
+        assert_eq!(signature, forged.private.sign(&m));
+    }
+
+    #[test]
+    fn ecdsa_dsks_works() {
+        let curve = Curve::p256();
+        let original = curve.generate_keypair();
+        let msg = b"Transfer $100 to Bob";
+        let signature = ecdsa::sign(&curve, &original, msg);
+
+        let (forged_curve, forged) = ecdsa_dsks(&curve, &original.public, msg, &signature).unwrap();
+        assert_ne!(original.public, forged.public);
+        assert!(forged_curve.is_valid_public_key(&forged.public));
+        assert!(ecdsa::verify(&forged_curve, &forged.public, msg, &signature));
+        assert!(!ecdsa::verify(&forged_curve, &forged.public, b"Transfer $100 to Eve", &signature));
+    }
+}
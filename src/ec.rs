@@ -0,0 +1,397 @@
+use num_bigint::BigUint;
+use num_traits::{One, ToPrimitive, Zero};
+use openssl::sha;
+
+use block;
+use dlog::Group;
+use numtheory;
+use util;
+
+const CRYPTOPALS_P: &str = "233970423115425145524320034830162017933";
+const CRYPTOPALS_A: u32 = 95051;
+const CRYPTOPALS_B: u32 = 11279326;
+const CRYPTOPALS_GX: u32 = 182;
+const CRYPTOPALS_GY: &str = "85518893674295321206118380980485522083";
+const CRYPTOPALS_N: &str = "29246302889428143187362802287225875743";
+const CRYPTOPALS_CURVE_ORDER: &str = "233970423115425145498902418297807005944";
+
+const P256_P: &str = "ffffffff00000001000000000000000000000000ffffffffffffffffffffffff";
+const P256_A: &str = "ffffffff00000001000000000000000000000000fffffffffffffffffffffffc";
+const P256_B: &str = "5ac635d8aa3a93e7b3ebbd55769886bc651d06b0cc53b0f63bce3c3e27d2604b";
+const P256_GX: &str = "6b17d1f2e12c4247f8bce6e563a440f277037d812deb33a0f4a13945d898c296";
+const P256_GY: &str = "4fe342e2fe1a7f9b8ee7eb4a7c0f9e162bce33576b315ececbb6406837bf51f5";
+const P256_N: &str = "ffffffff00000000ffffffffffffffffbce6faada7179e84f3b9cac2fc632551";
+
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+pub enum Point {
+    Infinity,
+    Affine { x: BigUint, y: BigUint },
+}
+
+impl Point {
+    pub fn new(x: BigUint, y: BigUint) -> Point {
+        Point::Affine { x, y }
+    }
+}
+
+/// Point in Jacobian coordinates: (X, Y, Z) stands for (X / Z^2, Y / Z^3), Z = 0 is the point at infinity
+#[derive(Clone, Debug)]
+pub struct JacobianPoint {
+    pub x: BigUint,
+    pub y: BigUint,
+    pub z: BigUint,
+}
+
+/// y^2 = x^3 + ax + b over GF(p), with a base point `g` of prime order `n`
+#[derive(Clone, PartialEq, Debug)]
+pub struct Curve {
+    pub p: BigUint,
+    pub a: BigUint,
+    pub b: BigUint,
+    pub g: Point,
+    pub n: BigUint,
+    /// Number of points on the whole curve, `n` times the cofactor
+    pub order: BigUint,
+}
+
+impl Curve {
+    pub fn new(p: BigUint, a: BigUint, b: BigUint, g: Point, n: BigUint, order: BigUint) -> Curve {
+        Curve { a: a % &p, b: b % &p, p, g, n, order }
+    }
+
+    /// y^2 = x^3 - 95051x + 11279326
+    pub fn cryptopals() -> Curve {
+        let p = BigUint::parse_bytes(CRYPTOPALS_P.as_bytes(), 10).unwrap();
+        let a = &p - CRYPTOPALS_A;
+        let g = Point::new(BigUint::from(CRYPTOPALS_GX), BigUint::parse_bytes(CRYPTOPALS_GY.as_bytes(), 10).unwrap());
+        Curve::new(p, a, BigUint::from(CRYPTOPALS_B), g,
+                   BigUint::parse_bytes(CRYPTOPALS_N.as_bytes(), 10).unwrap(),
+                   BigUint::parse_bytes(CRYPTOPALS_CURVE_ORDER.as_bytes(), 10).unwrap())
+    }
+
+    pub fn p256() -> Curve {
+        let hex = |s: &str| BigUint::parse_bytes(s.as_bytes(), 16).unwrap();
+        let n = hex(P256_N);
+        Curve::new(hex(P256_P), hex(P256_A), hex(P256_B), Point::new(hex(P256_GX), hex(P256_GY)), n.clone(), n)
+    }
+
+    pub fn field_bytes(&self) -> usize {
+        (self.p.bits() as usize).div_ceil(8)
+    }
+
+    fn sub(&self, a: &BigUint, b: &BigUint) -> BigUint {
+        (a + &self.p - (b % &self.p)) % &self.p
+    }
+
+    fn inv(&self, a: &BigUint) -> BigUint {
+        numtheory::invmod(a, &self.p).unwrap()
+    }
+
+    pub fn is_on_curve(&self, point: &Point) -> bool {
+        match *point {
+            Point::Infinity => true,
+            Point::Affine { ref x, ref y } => {
+                x < &self.p && y < &self.p &&
+                    (y * y) % &self.p == (x * x * x + &self.a * x + &self.b) % &self.p
+            }
+        }
+    }
+
+    /// On the curve and in the subgroup generated by `g`, i.e. a public key that is safe to use
+    pub fn is_valid_public_key(&self, point: &Point) -> bool {
+        *point != Point::Infinity && self.is_on_curve(point) && self.scalar_mul(point, &self.n) == Point::Infinity
+    }
+
+    /// A point with the given x coordinate, if x^3 + ax + b is a square
+    pub fn lift_x(&self, x: &BigUint) -> Option<Point> {
+        let x = x % &self.p;
+        let rhs = (&x * &x * &x + &self.a * &x + &self.b) % &self.p;
+        numtheory::sqrt_mod(&rhs, &self.p).map(|y| Point::new(x, y))
+    }
+
+    pub fn random_point(&self) -> Point {
+        loop {
+            if let Some(point) = self.lift_x(&util::generate_random_biguint_below(&self.p)) {
+                return point;
+            }
+        }
+    }
+
+    pub fn neg(&self, point: &Point) -> Point {
+        match *point {
+            Point::Infinity => Point::Infinity,
+            Point::Affine { ref x, ref y } => Point::new(x.clone(), self.sub(&BigUint::zero(), y)),
+        }
+    }
+
+    /// Affine addition, one field inversion per call
+    pub fn add(&self, p1: &Point, p2: &Point) -> Point {
+        let (x1, y1, x2, y2) = match (p1, p2) {
+            (Point::Infinity, _) => return p2.clone(),
+            (_, Point::Infinity) => return p1.clone(),
+            (Point::Affine { x: x1, y: y1 }, Point::Affine { x: x2, y: y2 }) => (x1, y1, x2, y2),
+        };
+        if x1 == x2 && (y1 + y2) % &self.p == BigUint::zero() {
+            return Point::Infinity;
+        }
+
+        let m = if x1 == x2 {
+            (BigUint::from(3u32) * x1 * x1 + &self.a) * self.inv(&(y1 * 2u32)) % &self.p
+        } else {
+            self.sub(y2, y1) * self.inv(&self.sub(x2, x1)) % &self.p
+        };
+        let x3 = self.sub(&self.sub(&(&m * &m), x1), x2);
+        let y3 = self.sub(&(&m * self.sub(x1, &x3)), y1);
+        Point::new(x3, y3)
+    }
+
+    pub fn to_jacobian(&self, point: &Point) -> JacobianPoint {
+        match *point {
+            Point::Infinity => JacobianPoint { x: BigUint::one(), y: BigUint::one(), z: BigUint::zero() },
+            Point::Affine { ref x, ref y } => JacobianPoint { x: x.clone(), y: y.clone(), z: BigUint::one() },
+        }
+    }
+
+    pub fn to_affine(&self, point: &JacobianPoint) -> Point {
+        if point.z.is_zero() {
+            return Point::Infinity;
+        }
+        let z_inv = self.inv(&point.z);
+        let z_inv2 = &z_inv * &z_inv % &self.p;
+        Point::new(&point.x * &z_inv2 % &self.p, &point.y * z_inv2 * z_inv % &self.p)
+    }
+
+    /// dbl-2007-bl, without inversions
+    pub fn jacobian_double(&self, point: &JacobianPoint) -> JacobianPoint {
+        if point.z.is_zero() || point.y.is_zero() {
+            return self.to_jacobian(&Point::Infinity);
+        }
+        let p = &self.p;
+        let xx = &point.x * &point.x % p;
+        let yy = &point.y * &point.y % p;
+        let yyyy = &yy * &yy % p;
+        let zz = &point.z * &point.z % p;
+        let s = BigUint::from(2u32) * self.sub(&self.sub(&((&point.x + &yy) * (&point.x + &yy)), &xx), &yyyy) % p;
+        let m = (BigUint::from(3u32) * &xx + &self.a * &zz * &zz) % p;
+        let x3 = self.sub(&(&m * &m), &(BigUint::from(2u32) * &s));
+        let y3 = self.sub(&(&m * self.sub(&s, &x3)), &(BigUint::from(8u32) * yyyy));
+        let z3 = self.sub(&self.sub(&((&point.y + &point.z) * (&point.y + &point.z)), &yy), &zz);
+        JacobianPoint { x: x3, y: y3, z: z3 }
+    }
+
+    /// add-2007-bl, falling back to doubling for equal points
+    pub fn jacobian_add(&self, p1: &JacobianPoint, p2: &JacobianPoint) -> JacobianPoint {
+        if p1.z.is_zero() {
+            return p2.clone();
+        }
+        if p2.z.is_zero() {
+            return p1.clone();
+        }
+        let p = &self.p;
+        let z1z1 = &p1.z * &p1.z % p;
+        let z2z2 = &p2.z * &p2.z % p;
+        let u1 = &p1.x * &z2z2 % p;
+        let u2 = &p2.x * &z1z1 % p;
+        let s1 = &p1.y * &p2.z * &z2z2 % p;
+        let s2 = &p2.y * &p1.z * &z1z1 % p;
+        if u1 == u2 {
+            return if s1 == s2 { self.jacobian_double(p1) } else { self.to_jacobian(&Point::Infinity) };
+        }
+
+        let h = self.sub(&u2, &u1);
+        let i = BigUint::from(4u32) * &h * &h % p;
+        let j = &h * &i % p;
+        let r = BigUint::from(2u32) * self.sub(&s2, &s1) % p;
+        let v = &u1 * &i % p;
+        let x3 = self.sub(&self.sub(&(&r * &r), &j), &(BigUint::from(2u32) * &v));
+        let y3 = self.sub(&(&r * self.sub(&v, &x3)), &(BigUint::from(2u32) * s1 * j));
+        let z3 = self.sub(&self.sub(&((&p1.z + &p2.z) * (&p1.z + &p2.z)), &z1z1), &z2z2) * h % p;
+        JacobianPoint { x: x3, y: y3, z: z3 }
+    }
+
+    /// Double-and-add in Jacobian coordinates, a single inversion at the end
+    pub fn scalar_mul(&self, point: &Point, k: &BigUint) -> Point {
+        let base = self.to_jacobian(point);
+        let mut result = self.to_jacobian(&Point::Infinity);
+        for i in (0..k.bits()).rev() {
+            result = self.jacobian_double(&result);
+            if k.bit(i) {
+                result = self.jacobian_add(&result, &base);
+            }
+        }
+        self.to_affine(&result)
+    }
+
+    /// Double-and-add with affine formulas, slower but straightforward
+    pub fn scalar_mul_affine(&self, point: &Point, k: &BigUint) -> Point {
+        let mut result = Point::Infinity;
+        for i in (0..k.bits()).rev() {
+            result = self.add(&result, &result);
+            if k.bit(i) {
+                result = self.add(&result, point);
+            }
+        }
+        result
+    }
+
+    pub fn generate_keypair(&self) -> EcKeyPair {
+        let private = util::generate_random_biguint_below(&(&self.n - 1u32)) + 1u32;
+        self.keypair_from_private(private)
+    }
+
+    pub fn keypair_from_private(&self, private: BigUint) -> EcKeyPair {
+        let public = self.scalar_mul(&self.g, &private);
+        EcKeyPair { private, public }
+    }
+
+    /// No validation of `other_public`, see `is_valid_public_key`
+    pub fn shared_secret(&self, keypair: &EcKeyPair, other_public: &Point) -> Point {
+        self.scalar_mul(other_public, &keypair.private)
+    }
+}
+
+/// Curve points under addition, for the generic discrete log algorithms
+impl Group for Curve {
+    type Element = Point;
+
+    fn identity(&self) -> Point {
+        Point::Infinity
+    }
+
+    fn combine(&self, a: &Point, b: &Point) -> Point {
+        self.add(a, b)
+    }
+
+    fn invert(&self, a: &Point) -> Point {
+        self.neg(a)
+    }
+
+    fn exp(&self, a: &Point, n: &BigUint) -> Point {
+        self.scalar_mul(a, n)
+    }
+
+    fn jump_index(&self, a: &Point, k: u32) -> u32 {
+        match *a {
+            Point::Infinity => 0,
+            Point::Affine { ref x, .. } => (x % k).to_u32().unwrap(),
+        }
+    }
+}
+
+pub struct EcKeyPair {
+    private: BigUint,
+    pub public: Point,
+}
+
+impl EcKeyPair {
+    /// For signature schemes built on top of the curve
+    pub fn private_key(&self) -> &BigUint {
+        &self.private
+    }
+}
+
+/// SEC1 uncompressed encoding, 0x04 || x || y, a single zero byte for the point at infinity
+pub fn point_bytes(curve: &Curve, point: &Point) -> Vec<u8> {
+    match *point {
+        Point::Infinity => vec![0],
+        Point::Affine { ref x, ref y } => {
+            let mut encoded = vec![4];
+            for coordinate in &[x, y] {
+                let bytes = coordinate.to_bytes_be();
+                encoded.extend(vec![0u8; curve.field_bytes().saturating_sub(bytes.len())]);
+                encoded.extend_from_slice(&bytes);
+            }
+            encoded
+        }
+    }
+}
+
+/// KDF: SHA256 of the x coordinate padded to the field size, truncated to an AES-128 key
+pub fn derive_aes_key(curve: &Curve, shared_secret: &Point) -> Vec<u8> {
+    let x = match *shared_secret {
+        Point::Infinity => Vec::new(),
+        Point::Affine { ref x, .. } => x.to_bytes_be(),
+    };
+    let mut padded = vec![0u8; curve.field_bytes().saturating_sub(x.len())];
+    padded.extend_from_slice(&x);
+    sha::sha256(&padded)[..block::BLOCK_SIZE].to_vec()
+}
+
+/// AES-CBC under the derived key, the random IV is appended to the ciphertext
+pub fn encrypt_message(curve: &Curve, shared_secret: &Point, msg: &[u8]) -> Vec<u8> {
+    let key = derive_aes_key(curve, shared_secret);
+    let iv = util::generate_random_bytes(block::BLOCK_SIZE);
+    let mut encrypted = block::aes_cbc_encrypt(&key, msg, &iv, block::Padding::PKCS7).unwrap();
+    encrypted.extend_from_slice(&iv);
+    encrypted
+}
+
+pub fn decrypt_message(curve: &Curve, shared_secret: &Point, data: &[u8]) -> Option<Vec<u8>> {
+    if data.len() < 2 * block::BLOCK_SIZE || !data.len().is_multiple_of(block::BLOCK_SIZE) {
+        return None;
+    }
+    let key = derive_aes_key(curve, shared_secret);
+    let (cipher_text, iv) = data.split_at(data.len() - block::BLOCK_SIZE);
+    block::aes_cbc_decrypt(&key, cipher_text, iv, block::Padding::PKCS7)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn base_points_are_valid() {
+        for curve in &[Curve::cryptopals(), Curve::p256()] {
+            assert!(curve.is_on_curve(&curve.g));
+            assert!(curve.is_valid_public_key(&curve.g));
+            assert_eq!(Point::Infinity, curve.scalar_mul(&curve.g, &curve.n));
+        }
+    }
+
+    #[test]
+    fn random_point_works() {
+        let curve = Curve::cryptopals();
+        let point = curve.random_point();
+        assert!(curve.is_on_curve(&point));
+        assert_eq!(Point::Infinity, curve.scalar_mul(&point, &curve.order));
+    }
+
+    #[test]
+    fn jacobian_matches_affine() {
+        let curve = Curve::cryptopals();
+        let k = BigUint::parse_bytes(b"1234567890123456789012345", 10).unwrap();
+        let expected = curve.scalar_mul_affine(&curve.g, &k);
+        assert!(curve.is_on_curve(&expected));
+        assert_eq!(expected, curve.scalar_mul(&curve.g, &k));
+
+        let g2 = curve.add(&curve.g, &curve.g);
+        assert_eq!(g2, curve.to_affine(&curve.jacobian_double(&curve.to_jacobian(&curve.g))));
+        assert_eq!(Point::Infinity, curve.add(&curve.g, &curve.neg(&curve.g)));
+    }
+
+    #[test]
+    fn p256_scalar_mul_works() {
+        // NIST P-256 test vector, k = 2
+        let curve = Curve::p256();
+        let expected = Point::new(
+            BigUint::parse_bytes(b"7cf27b188d034f7e8a52380304b51ac3c08969e277f21b35a60b48fc47669978", 16).unwrap(),
+            BigUint::parse_bytes(b"07775510db8ed040293d9ac69f7430dbba7dade63ce982299e04b79d227873d1", 16).unwrap());
+        assert_eq!(expected, curve.scalar_mul(&curve.g, &BigUint::from(2u32)));
+    }
+
+    #[test]
+    fn ecdh_channel_works() {
+        for curve in &[Curve::cryptopals(), Curve::p256()] {
+            let alice = curve.generate_keypair();
+            let bob = curve.generate_keypair();
+            let alice_secret = curve.shared_secret(&alice, &bob.public);
+            assert_eq!(alice_secret, curve.shared_secret(&bob, &alice.public));
+
+            let bob_secret = curve.shared_secret(&bob, &alice.public);
+            for &msg in &[&b"Elliptic curves are just groups too"[..], b"hi", b"YELLOW SUBMARINE"] {
+                let encrypted = encrypt_message(curve, &alice_secret, msg);
+                assert_eq!(Some(msg.to_vec()), decrypt_message(curve, &bob_secret, &encrypted));
+            }
+        }
+    }
+}
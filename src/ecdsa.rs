@@ -0,0 +1,93 @@
+use num_bigint::BigUint;
+use num_traits::Zero;
+use openssl::sha;
+
+use ec::{Curve, EcKeyPair, Point};
+use numtheory;
+use util;
+
+const SHA256_BITS: u64 = 256;
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct EcdsaSignature {
+    pub r: BigUint,
+    pub s: BigUint,
+}
+
+/// SHA-256 of the message, truncated to the bit length of n
+pub fn hash_message(curve: &Curve, msg: &[u8]) -> BigUint {
+    let h = BigUint::from_bytes_be(&sha::sha256(msg));
+    let n_bits = curve.n.bits();
+    if n_bits < SHA256_BITS {
+        h >> (SHA256_BITS - n_bits)
+    } else {
+        h
+    }
+}
+
+pub fn sign(curve: &Curve, keypair: &EcKeyPair, msg: &[u8]) -> EcdsaSignature {
+    loop {
+        let k = util::generate_random_biguint_below(&(&curve.n - 1u32)) + 1u32;
+        if let Some(signature) = sign_with_nonce(curve, keypair.private_key(), &k, msg) {
+            return signature;
+        }
+    }
+}
+
+/// r = x(kG) mod n, s = (H(m) + dr) / k mod n, `None` when r or s comes out 0
+pub fn sign_with_nonce(curve: &Curve, d: &BigUint, k: &BigUint, msg: &[u8]) -> Option<EcdsaSignature> {
+    let r = match curve.scalar_mul(&curve.g, k) {
+        Point::Infinity => return None,
+        Point::Affine { x, .. } => x % &curve.n,
+    };
+    let k_inv = numtheory::invmod(k, &curve.n)?;
+    let s = k_inv * (hash_message(curve, msg) + d * &r) % &curve.n;
+    if r.is_zero() || s.is_zero() {
+        return None;
+    }
+    Some(EcdsaSignature { r, s })
+}
+
+/// Accepts when x(H(m) / s * G + r / s * Q) = r mod n
+pub fn verify(curve: &Curve, public: &Point, msg: &[u8], signature: &EcdsaSignature) -> bool {
+    let n = &curve.n;
+    if signature.r.is_zero() || signature.r >= *n || signature.s.is_zero() || signature.s >= *n {
+        return false;
+    }
+    let w = match numtheory::invmod(&signature.s, n) {
+        Some(w) => w,
+        None => return false,
+    };
+    let u1 = hash_message(curve, msg) * &w % n;
+    let u2 = &signature.r * w % n;
+    match curve.add(&curve.scalar_mul(&curve.g, &u1), &curve.scalar_mul(public, &u2)) {
+        Point::Infinity => false,
+        Point::Affine { x, .. } => x % n == signature.r,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sign_and_verify() {
+        for curve in &[Curve::cryptopals(), Curve::p256()] {
+            let keypair = curve.generate_keypair();
+            let signature = sign(curve, &keypair, b"hi mom");
+            assert!(verify(curve, &keypair.public, b"hi mom", &signature));
+            assert!(!verify(curve, &keypair.public, b"hi dad", &signature));
+            assert!(!verify(curve, &curve.generate_keypair().public, b"hi mom", &signature));
+        }
+    }
+
+    #[test]
+    fn sign_with_nonce_is_deterministic() {
+        let curve = Curve::p256();
+        let keypair = curve.generate_keypair();
+        let k = BigUint::from(123_456_789u32);
+        let signature = sign_with_nonce(&curve, keypair.private_key(), &k, b"hi mom").unwrap();
+        assert_eq!(Some(signature.clone()), sign_with_nonce(&curve, keypair.private_key(), &k, b"hi mom"));
+        assert!(verify(&curve, &keypair.public, b"hi mom", &signature));
+    }
+}
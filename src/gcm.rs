@@ -0,0 +1,249 @@
+use block;
+use gf128;
+use gf128::{Gf128, Polynomial};
+use util;
+
+pub const NONCE_SIZE: usize = 12;
+pub const TAG_SIZE: usize = 16;
+
+/// H = E_K(0^128)
+pub fn authentication_key(key: &[u8]) -> Option<Gf128> {
+    block::aes_encrypt_block(key, &[0; block::BLOCK_SIZE]).ok().map(|h| Gf128::from_block(&h))
+}
+
+/// nonce || counter, the 96-bit nonce form of the pre-counter block
+fn counter_block(nonce: &[u8], counter: u32) -> Vec<u8> {
+    let mut block = nonce.to_vec();
+    block.extend_from_slice(&counter.to_be_bytes());
+    block
+}
+
+/// CTR mode starting at `first_counter`, the counter is the last 32 bits of the block
+fn gctr(key: &[u8], nonce: &[u8], first_counter: u32, data: &[u8]) -> Option<Vec<u8>> {
+    let mut result = Vec::with_capacity(data.len());
+    for (i, chunk) in data.chunks(block::BLOCK_SIZE).enumerate() {
+        let keystream = block::aes_encrypt_block(key, &counter_block(nonce, first_counter.wrapping_add(i as u32))).ok()?;
+        result.extend_from_slice(&util::xor_bufs(chunk, &keystream[..chunk.len()]));
+    }
+    Some(result)
+}
+
+/// GHASH input: the associated data and the ciphertext, each zero padded to whole blocks, then the block
+/// with their bit lengths
+pub fn ghash_blocks(aad: &[u8], ciphertext: &[u8]) -> Vec<Gf128> {
+    let mut blocks = Vec::new();
+    for data in &[aad, ciphertext] {
+        for chunk in data.chunks(block::BLOCK_SIZE) {
+            let mut padded = chunk.to_vec();
+            padded.resize(block::BLOCK_SIZE, 0);
+            blocks.push(Gf128::from_block(&padded));
+        }
+    }
+    let mut lengths = ((aad.len() as u64) * 8).to_be_bytes().to_vec();
+    lengths.extend_from_slice(&((ciphertext.len() as u64) * 8).to_be_bytes());
+    blocks.push(Gf128::from_block(&lengths));
+    blocks
+}
+
+/// Horner evaluation: b_1 H^m + b_2 H^(m - 1) + ... + b_m H
+pub fn ghash(h: Gf128, aad: &[u8], ciphertext: &[u8]) -> Gf128 {
+    ghash_blocks(aad, ciphertext).into_iter().fold(Gf128::zero(), |acc, b| (acc + b) * h)
+}
+
+/// GHASH masked with E_K(nonce || 1)
+pub fn compute_tag(key: &[u8], nonce: &[u8], aad: &[u8], ciphertext: &[u8]) -> Option<Vec<u8>> {
+    if nonce.len() != NONCE_SIZE {
+        return None;
+    }
+    let h = authentication_key(key)?;
+    let mask = block::aes_encrypt_block(key, &counter_block(nonce, 1)).ok()?;
+    Some(util::xor_bufs(&ghash(h, aad, ciphertext).to_block(), &mask))
+}
+
+/// AES-GCM with a 96-bit nonce, returns (ciphertext, tag)
+pub fn gcm_encrypt(key: &[u8], nonce: &[u8], plaintext: &[u8], aad: &[u8]) -> Option<(Vec<u8>, Vec<u8>)> {
+    assert_eq!(NONCE_SIZE, nonce.len());
+    let ciphertext = gctr(key, nonce, 2, plaintext)?;
+    let tag = compute_tag(key, nonce, aad, &ciphertext)?;
+    Some((ciphertext, tag))
+}
+
+/// `None` unless the tag verifies
+pub fn gcm_decrypt(key: &[u8], nonce: &[u8], ciphertext: &[u8], aad: &[u8], tag: &[u8]) -> Option<Vec<u8>> {
+    if compute_tag(key, nonce, aad, ciphertext)? != tag {
+        return None;
+    }
+    gctr(key, nonce, 2, ciphertext)
+}
+
+/// An authenticated message as seen on the wire
+#[derive(Clone, PartialEq, Debug)]
+pub struct GcmMessage {
+    pub nonce: Vec<u8>,
+    pub aad: Vec<u8>,
+    pub ciphertext: Vec<u8>,
+    pub tag: Vec<u8>,
+}
+
+/// Sends under a fixed key and, wrongly, a fixed nonce. Also the receiving end that checks tags.
+pub struct NonceReusingSender {
+    key: Vec<u8>,
+    nonce: Vec<u8>,
+}
+
+impl Default for NonceReusingSender {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl NonceReusingSender {
+    pub fn new() -> NonceReusingSender {
+        NonceReusingSender {
+            key: util::generate_random_bytes(block::BLOCK_SIZE),
+            nonce: util::generate_random_bytes(NONCE_SIZE),
+        }
+    }
+
+    pub fn encrypt(&self, plaintext: &[u8], aad: &[u8]) -> GcmMessage {
+        let (ciphertext, tag) = gcm_encrypt(&self.key, &self.nonce, plaintext, aad).unwrap();
+        GcmMessage { nonce: self.nonce.clone(), aad: aad.to_vec(), ciphertext, tag }
+    }
+
+    pub fn decrypt(&self, msg: &GcmMessage) -> Option<Vec<u8>> {
+        gcm_decrypt(&self.key, &msg.nonce, &msg.ciphertext, &msg.aad, &msg.tag)
+    }
+}
+
+/// b_1 X^m + ... + b_m X + t, which is s = E_K(nonce || 1) at X = H
+pub fn tag_polynomial(msg: &GcmMessage) -> Polynomial {
+    let mut coeffs = vec![Gf128::from_block(&msg.tag)];
+    coeffs.extend(ghash_blocks(&msg.aad, &msg.ciphertext).into_iter().rev());
+    Polynomial::new(coeffs)
+}
+
+/// The forbidden attack: two messages under the same nonce share s, so H is a root of the difference of
+/// their tag polynomials. Every further message rules out spurious roots; whatever candidates remain can be
+/// told apart by trying a forgery with each. Repeats of the first message say nothing about H and are skipped.
+pub fn recover_authentication_key(messages: &[GcmMessage]) -> Vec<Gf128> {
+    if messages.is_empty() {
+        return Vec::new();
+    }
+    let first = tag_polynomial(&messages[0]);
+    let others: Vec<Polynomial> = messages[1..].iter().map(tag_polynomial).filter(|other| *other != first).collect();
+    if others.is_empty() {
+        return Vec::new();
+    }
+    let mut candidates = gf128::roots(&(&first - &others[0]));
+    for other in &others[1..] {
+        candidates.retain(|&h| first.eval(h) == other.eval(h));
+    }
+    candidates
+}
+
+/// A valid tag for new associated data and ciphertext under the reused nonce, from H and one known message
+pub fn forge_message(h: Gf128, known: &GcmMessage, aad: &[u8], ciphertext: &[u8]) -> GcmMessage {
+    let s = tag_polynomial(known).eval(h);
+    let tag = (ghash(h, aad, ciphertext) + s).to_block();
+    GcmMessage { nonce: known.nonce.clone(), aad: aad.to_vec(), ciphertext: ciphertext.to_vec(), tag }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use openssl::symm;
+
+    #[test]
+    fn gcm_test_vectors() {
+        // McGrew-Viega test cases 2 and 4
+        let zero = [0u8; 16];
+        let (ciphertext, tag) = gcm_encrypt(&zero, &zero[..NONCE_SIZE], &zero, &[]).unwrap();
+        assert_eq!("0388dace60b6a392f328c2b971b2fe78", util::binary_to_hex(&ciphertext));
+        assert_eq!("ab6e47d42cec13bdf53a67b21257bddf", util::binary_to_hex(&tag));
+
+        let key = util::hex_to_binary("feffe9928665731c6d6a8f9467308308").unwrap();
+        let nonce = util::hex_to_binary("cafebabefacedbaddecaf888").unwrap();
+        let plaintext = util::hex_to_binary("d9313225f88406e5a55909c5aff5269a86a7a9531534f7da2e4c303d8a318a721c3c0c95956809532fcf0e2449a6b525b16aedf5aa0de657ba637b39").unwrap();
+        let aad = util::hex_to_binary("feedfacedeadbeeffeedfacedeadbeefabaddad2").unwrap();
+        let (ciphertext, tag) = gcm_encrypt(&key, &nonce, &plaintext, &aad).unwrap();
+        assert_eq!("42831ec2217774244b7221b784d0d49ce3aa212f2c02a4e035c17e2329aca12e21d514b25466931c7d8f6a5aac84aa051ba30b396a0aac973d58e091", util::binary_to_hex(&ciphertext));
+        assert_eq!("5bc94fbc3221a5db94fae95ae7121a47", util::binary_to_hex(&tag));
+        assert_eq!(Some(plaintext), gcm_decrypt(&key, &nonce, &ciphertext, &aad, &tag));
+    }
+
+    #[test]
+    fn gcm_matches_openssl() {
+        let key = util::generate_random_bytes(16);
+        let nonce = util::generate_random_bytes(NONCE_SIZE);
+        let plaintext = util::generate_random_bytes(77);
+        let aad = util::generate_random_bytes(21);
+        let mut expected_tag = vec![0u8; TAG_SIZE];
+        let expected = symm::encrypt_aead(symm::Cipher::aes_128_gcm(), &key, Some(&nonce), &aad, &plaintext, &mut expected_tag).unwrap();
+        assert_eq!(Some((expected.clone(), expected_tag.clone())), gcm_encrypt(&key, &nonce, &plaintext, &aad));
+
+        let mut bad_tag = expected_tag.clone();
+        bad_tag[0] ^= 1;
+        assert_eq!(None, gcm_decrypt(&key, &nonce, &expected, &aad, &bad_tag));
+    }
+
+    #[test]
+    fn forbidden_attack_works() {
+        let sender = NonceReusingSender::new();
+        let messages = vec![
+            sender.encrypt(b"Attack at dawn, bring the big guns", b"to: HQ"),
+            sender.encrypt(b"Retreat", b"to: field office"),
+            sender.encrypt(b"The password is swordfish, don't tell anyone", b""),
+        ];
+        assert_eq!(authentication_key(&sender.key).into_iter().collect::<Vec<_>>(), recover_authentication_key(&messages));
+        let h = recover_authentication_key(&messages)[0];
+
+        let mut ciphertext = messages[0].ciphertext.clone();
+        ciphertext[0] ^= b'A' ^ b'R';
+        let forged = forge_message(h, &messages[0], b"to: everyone", &ciphertext);
+        let plaintext = sender.decrypt(&forged).unwrap();
+        assert!(plaintext.starts_with(b"Rttack at dawn"));
+    }
+
+    #[test]
+    fn forbidden_attack_with_two_messages_works() {
+        let sender = NonceReusingSender::new();
+        let messages = vec![
+            sender.encrypt(b"Attack at dawn, bring the big guns", b"to: HQ"),
+            sender.encrypt(b"Retreat to the hills and wait for orders", b"to: field office"),
+        ];
+        let candidates = recover_authentication_key(&messages);
+        assert!(candidates.contains(&authentication_key(&sender.key).unwrap()));
+
+        // only the real H produces a tag the receiver accepts
+        let accepted: Vec<Gf128> = candidates
+            .into_iter()
+            .filter(|&h| sender.decrypt(&forge_message(h, &messages[0], b"to: everyone", &messages[0].ciphertext)).is_some())
+            .collect();
+        assert_eq!(authentication_key(&sender.key).into_iter().collect::<Vec<_>>(), accepted);
+    }
+
+    #[test]
+    fn forbidden_attack_needs_two_messages() {
+        let sender = NonceReusingSender::new();
+        assert!(recover_authentication_key(&[sender.encrypt(b"Retreat", b"")]).is_empty());
+    }
+
+    #[test]
+    fn forbidden_attack_skips_repeated_messages() {
+        let sender = NonceReusingSender::new();
+        let msg = sender.encrypt(b"Retreat", b"");
+        assert!(recover_authentication_key(&[msg.clone(), msg.clone()]).is_empty());
+
+        let other = sender.encrypt(b"Attack at dawn, bring the big guns", b"to: HQ");
+        let candidates = recover_authentication_key(&[msg.clone(), msg.clone(), other]);
+        assert!(candidates.contains(&authentication_key(&sender.key).unwrap()));
+    }
+
+    #[test]
+    fn wrong_nonce_size_is_rejected() {
+        let sender = NonceReusingSender::new();
+        let mut msg = sender.encrypt(b"Retreat", b"");
+        msg.nonce.truncate(8);
+        assert_eq!(None, sender.decrypt(&msg));
+    }
+}
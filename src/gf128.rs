@@ -0,0 +1,447 @@
+use std::ops::{Add, Div, Mul, Sub};
+
+use block;
+use util;
+
+/// x^128 = x^7 + x^2 + x + 1
+const REDUCTION: u128 = 0x87;
+
+/// Element of GF(2^128) as used by GHASH. Bit i of the u128 is the coefficient of x^i; in the GCM byte
+/// encoding the first byte's top bit is the coefficient of x^0, hence the bit reversal when converting.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Default)]
+pub struct Gf128(pub u128);
+
+impl Gf128 {
+    pub fn zero() -> Gf128 {
+        Gf128(0)
+    }
+
+    pub fn one() -> Gf128 {
+        Gf128(1)
+    }
+
+    pub fn from_block(block: &[u8]) -> Gf128 {
+        let mut bytes = [0u8; block::BLOCK_SIZE];
+        bytes.copy_from_slice(block);
+        Gf128(u128::from_be_bytes(bytes).reverse_bits())
+    }
+
+    pub fn to_block(self) -> Vec<u8> {
+        self.0.reverse_bits().to_be_bytes().to_vec()
+    }
+
+    pub fn random() -> Gf128 {
+        Gf128::from_block(&util::generate_random_bytes(block::BLOCK_SIZE))
+    }
+
+    pub fn is_zero(self) -> bool {
+        self.0 == 0
+    }
+
+    pub fn pow(self, mut e: u128) -> Gf128 {
+        let mut result = Gf128::one();
+        let mut base = self;
+        while e != 0 {
+            if e & 1 == 1 {
+                result = result * base;
+            }
+            base = base * base;
+            e >>= 1;
+        }
+        result
+    }
+
+    /// a^(2^128 - 2), panics for 0
+    pub fn inverse(self) -> Gf128 {
+        assert!(!self.is_zero());
+        self.pow(u128::MAX - 1)
+    }
+
+    /// a^(2^127): squaring is a bijection in characteristic 2, this undoes it
+    pub fn sqrt(self) -> Gf128 {
+        (0..127).fold(self, |a, _| a * a)
+    }
+}
+
+impl Add for Gf128 {
+    type Output = Gf128;
+
+    #[allow(clippy::suspicious_arithmetic_impl)]
+    fn add(self, other: Gf128) -> Gf128 {
+        Gf128(self.0 ^ other.0)
+    }
+}
+
+/// Same as addition in characteristic 2
+impl Sub for Gf128 {
+    type Output = Gf128;
+
+    #[allow(clippy::suspicious_arithmetic_impl)]
+    fn sub(self, other: Gf128) -> Gf128 {
+        Gf128(self.0 ^ other.0)
+    }
+}
+
+/// Shift-and-add with reduction after every shift
+impl Mul for Gf128 {
+    type Output = Gf128;
+
+    fn mul(self, other: Gf128) -> Gf128 {
+        let (mut a, mut b) = (self.0, other.0);
+        let mut result = 0u128;
+        while b != 0 {
+            if b & 1 == 1 {
+                result ^= a;
+            }
+            b >>= 1;
+            let carry = a >> 127;
+            a <<= 1;
+            if carry == 1 {
+                a ^= REDUCTION;
+            }
+        }
+        Gf128(result)
+    }
+}
+
+impl Div for Gf128 {
+    type Output = Gf128;
+
+    #[allow(clippy::suspicious_arithmetic_impl)]
+    fn div(self, other: Gf128) -> Gf128 {
+        self * other.inverse()
+    }
+}
+
+/// Polynomial over GF(2^128), coefficients from the constant term up, without trailing zeros
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Polynomial {
+    coeffs: Vec<Gf128>,
+}
+
+impl Polynomial {
+    pub fn new(mut coeffs: Vec<Gf128>) -> Polynomial {
+        while coeffs.last().is_some_and(|c| c.is_zero()) {
+            coeffs.pop();
+        }
+        Polynomial { coeffs }
+    }
+
+    pub fn zero() -> Polynomial {
+        Polynomial::new(Vec::new())
+    }
+
+    pub fn one() -> Polynomial {
+        Polynomial::constant(Gf128::one())
+    }
+
+    pub fn constant(c: Gf128) -> Polynomial {
+        Polynomial::new(vec![c])
+    }
+
+    /// The polynomial x
+    pub fn x() -> Polynomial {
+        Polynomial::new(vec![Gf128::zero(), Gf128::one()])
+    }
+
+    /// x + root, the monic factor vanishing at `root`
+    pub fn linear(root: Gf128) -> Polynomial {
+        Polynomial::new(vec![root, Gf128::one()])
+    }
+
+    pub fn random(degree_bound: usize) -> Polynomial {
+        Polynomial::new((0..degree_bound).map(|_| Gf128::random()).collect())
+    }
+
+    pub fn coeffs(&self) -> &[Gf128] {
+        &self.coeffs
+    }
+
+    pub fn coeff(&self, i: usize) -> Gf128 {
+        self.coeffs.get(i).cloned().unwrap_or_default()
+    }
+
+    /// `None` for the zero polynomial
+    pub fn degree(&self) -> Option<usize> {
+        self.coeffs.len().checked_sub(1)
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.coeffs.is_empty()
+    }
+
+    pub fn is_one(&self) -> bool {
+        *self == Polynomial::one()
+    }
+
+    pub fn leading(&self) -> Gf128 {
+        self.coeffs.last().cloned().unwrap_or_default()
+    }
+
+    pub fn scale(&self, c: Gf128) -> Polynomial {
+        Polynomial::new(self.coeffs.iter().map(|&a| a * c).collect())
+    }
+
+    pub fn monic(&self) -> Polynomial {
+        if self.is_zero() {
+            return self.clone();
+        }
+        self.scale(self.leading().inverse())
+    }
+
+    pub fn eval(&self, x: Gf128) -> Gf128 {
+        self.coeffs.iter().rev().fold(Gf128::zero(), |acc, &c| acc * x + c)
+    }
+
+    /// Quotient and remainder, panics when dividing by zero
+    pub fn div_rem(&self, divisor: &Polynomial) -> (Polynomial, Polynomial) {
+        let divisor_degree = divisor.degree().expect("division by the zero polynomial");
+        let lead_inv = divisor.leading().inverse();
+        let mut remainder = self.coeffs.clone();
+        let mut quotient = vec![Gf128::zero(); self.coeffs.len().saturating_sub(divisor_degree)];
+        while remainder.len() > divisor_degree {
+            let shift = remainder.len() - 1 - divisor_degree;
+            let c = *remainder.last().unwrap() * lead_inv;
+            quotient[shift] = c;
+            for (i, &d) in divisor.coeffs.iter().enumerate() {
+                remainder[shift + i] = remainder[shift + i] - c * d;
+            }
+            remainder.pop();
+            while remainder.last().is_some_and(|c| c.is_zero()) {
+                remainder.pop();
+            }
+        }
+        (Polynomial::new(quotient), Polynomial::new(remainder))
+    }
+
+    pub fn rem(&self, modulus: &Polynomial) -> Polynomial {
+        self.div_rem(modulus).1
+    }
+
+    /// Monic greatest common divisor
+    pub fn gcd(&self, other: &Polynomial) -> Polynomial {
+        let (mut a, mut b) = (self.clone(), other.clone());
+        while !b.is_zero() {
+            let r = a.rem(&b);
+            a = b;
+            b = r;
+        }
+        a.monic()
+    }
+
+    /// Formal derivative: in characteristic 2 only the odd-degree terms survive
+    pub fn derivative(&self) -> Polynomial {
+        Polynomial::new(self.coeffs.iter()
+            .enumerate()
+            .skip(1)
+            .map(|(i, &c)| if i % 2 == 1 { c } else { Gf128::zero() })
+            .collect())
+    }
+
+    /// g with g^2 = self, for a polynomial with only even-degree terms (zero derivative)
+    fn sqrt(&self) -> Polynomial {
+        Polynomial::new(self.coeffs.iter().step_by(2).map(|c| c.sqrt()).collect())
+    }
+
+    /// self^(2^128) mod `modulus`, the Frobenius map of GF(2^128)
+    fn frobenius_mod(&self, modulus: &Polynomial) -> Polynomial {
+        let mut result = self.rem(modulus);
+        for _ in 0..128 {
+            result = (&result * &result).rem(modulus);
+        }
+        result
+    }
+}
+
+impl<'a> Add<&'a Polynomial> for &'a Polynomial {
+    type Output = Polynomial;
+
+    fn add(self, other: &Polynomial) -> Polynomial {
+        let len = self.coeffs.len().max(other.coeffs.len());
+        Polynomial::new((0..len).map(|i| self.coeff(i) + other.coeff(i)).collect())
+    }
+}
+
+impl<'a> Sub<&'a Polynomial> for &'a Polynomial {
+    type Output = Polynomial;
+
+    #[allow(clippy::suspicious_arithmetic_impl)]
+    fn sub(self, other: &Polynomial) -> Polynomial {
+        self + other
+    }
+}
+
+impl<'a> Mul<&'a Polynomial> for &'a Polynomial {
+    type Output = Polynomial;
+
+    fn mul(self, other: &Polynomial) -> Polynomial {
+        if self.is_zero() || other.is_zero() {
+            return Polynomial::zero();
+        }
+        let mut coeffs = vec![Gf128::zero(); self.coeffs.len() + other.coeffs.len() - 1];
+        for (i, &a) in self.coeffs.iter().enumerate() {
+            for (j, &b) in other.coeffs.iter().enumerate() {
+                coeffs[i + j] = coeffs[i + j] + a * b;
+            }
+        }
+        Polynomial::new(coeffs)
+    }
+}
+
+impl<'a> Div<&'a Polynomial> for &'a Polynomial {
+    type Output = Polynomial;
+
+    fn div(self, other: &Polynomial) -> Polynomial {
+        self.div_rem(other).0
+    }
+}
+
+/// Square-free factorization of a monic polynomial: (g, i) pairs with square-free, pairwise coprime g such
+/// that f is the product of all g^i
+pub fn square_free_factorization(f: &Polynomial) -> Vec<(Polynomial, usize)> {
+    let mut factors = Vec::new();
+    let mut c = f.gcd(&f.derivative());
+    let mut w = f / &c;
+    let mut i = 1;
+    while !w.is_one() {
+        let y = w.gcd(&c);
+        let factor = &w / &y;
+        if !factor.is_one() {
+            factors.push((factor, i));
+        }
+        w = y;
+        c = &c / &w;
+        i += 1;
+    }
+    // what is left is a square
+    if !c.is_one() {
+        for (factor, multiplicity) in square_free_factorization(&c.sqrt()) {
+            factors.push((factor, multiplicity * 2));
+        }
+    }
+    factors
+}
+
+/// Distinct-degree factorization of a monic square-free polynomial: (g, d) pairs where g is the product of
+/// all irreducible factors of degree d, found as gcd(f, x^(q^d) - x) with q = 2^128
+pub fn distinct_degree_factorization(f: &Polynomial) -> Vec<(Polynomial, usize)> {
+    let mut factors = Vec::new();
+    let mut rest = f.clone();
+    let mut frobenius = Polynomial::x();
+    let mut d = 1;
+    while rest.degree().unwrap_or(0) >= 2 * d {
+        frobenius = frobenius.frobenius_mod(&rest);
+        let g = rest.gcd(&(&frobenius - &Polynomial::x()));
+        if !g.is_one() {
+            rest = &rest / &g;
+            frobenius = frobenius.rem(&rest);
+            factors.push((g, d));
+        }
+        d += 1;
+    }
+    if let Some(degree) = rest.degree().filter(|&degree| degree > 0) {
+        factors.push((rest, degree));
+    }
+    factors
+}
+
+/// Cantor-Zassenhaus equal-degree factorization of a monic product of irreducible factors of degree d. In
+/// characteristic 2 the splitting polynomial is the trace a + a^2 + ... + a^(2^(128d - 1)) of a random a,
+/// which is 0 or 1 modulo each factor.
+pub fn equal_degree_factorization(f: &Polynomial, d: usize) -> Vec<Polynomial> {
+    let degree = f.degree().unwrap_or(0);
+    if degree <= d {
+        return vec![f.clone()];
+    }
+    loop {
+        let a = Polynomial::random(degree);
+        let mut power = a.rem(f);
+        let mut trace = power.clone();
+        for _ in 1..128 * d {
+            power = (&power * &power).rem(f);
+            trace = &trace + &power;
+        }
+        let g = f.gcd(&trace);
+        let g_degree = g.degree().unwrap_or(0);
+        if g_degree > 0 && g_degree < degree {
+            let mut factors = equal_degree_factorization(&g, d);
+            factors.extend(equal_degree_factorization(&(f / &g), d));
+            return factors;
+        }
+    }
+}
+
+/// Monic irreducible factors with multiplicities
+pub fn factor(f: &Polynomial) -> Vec<(Polynomial, usize)> {
+    let mut factors = Vec::new();
+    for (square_free, multiplicity) in square_free_factorization(&f.monic()) {
+        for (product, d) in distinct_degree_factorization(&square_free) {
+            for irreducible in equal_degree_factorization(&product, d) {
+                factors.push((irreducible, multiplicity));
+            }
+        }
+    }
+    factors
+}
+
+/// Distinct roots in GF(2^128): the constant terms of the linear factors
+pub fn roots(f: &Polynomial) -> Vec<Gf128> {
+    factor(f).into_iter()
+        .filter(|(g, _)| g.degree() == Some(1))
+        .map(|(g, _)| g.coeff(0))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn field_arithmetic_works() {
+        let a = Gf128::random();
+        let b = Gf128::random();
+        assert_eq!(a * b, b * a);
+        assert_eq!(Gf128::one(), a * a.inverse());
+        assert_eq!(a, (a * b) / b);
+        assert_eq!(a, (a * a).sqrt());
+        assert_eq!(a, Gf128::from_block(&a.to_block()));
+        // x^127 * x = x^128 = x^7 + x^2 + x + 1
+        assert_eq!(Gf128(REDUCTION), Gf128(1 << 127) * Gf128(2));
+    }
+
+    #[test]
+    fn polynomial_division_works() {
+        let a = Polynomial::random(6);
+        let b = Polynomial::random(3);
+        let (q, r) = a.div_rem(&b);
+        assert!(r.degree() < b.degree());
+        assert_eq!(a, &(&q * &b) + &r);
+        let c = Polynomial::random(3);
+        assert_eq!(c.monic(), (&a * &c).gcd(&(&b * &c)).gcd(&c));
+    }
+
+    #[test]
+    fn factor_works() {
+        let r1 = Gf128::random();
+        let r2 = Gf128::random();
+        let r3 = Gf128::random();
+        let quadratic = Polynomial::random(3).monic();
+        let f = [Polynomial::linear(r1), Polynomial::linear(r2), Polynomial::linear(r2), Polynomial::linear(r3)]
+            .iter()
+            .fold(quadratic.clone(), |acc, g| &acc * g);
+
+        let found = roots(&f);
+        for r in &[r1, r2, r3] {
+            assert!(found.contains(r));
+        }
+        for r in &found {
+            assert!(f.eval(*r).is_zero());
+        }
+
+        let factors = factor(&f);
+        let product = factors.iter()
+            .fold(Polynomial::one(), |acc, (g, i)| (0..*i).fold(acc, |acc, _| &acc * g));
+        assert_eq!(f, product);
+        assert!(factors.contains(&(Polynomial::linear(r2), 2)));
+    }
+}
@@ -0,0 +1,226 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+
+use block::BLOCK_SIZE;
+use mdhash;
+use mdhash::MdHash;
+use util;
+
+/// Pads herded prefixes to whole blocks, so the final message length only depends on the prefix block count
+const PREFIX_FILLER: u8 = b' ';
+/// Deepest structure `load` accepts, far more than any toy hash here can build
+pub const MAX_DEPTH: usize = 24;
+
+/// Binary tree of collisions: 2^depth leaf states, every level halving the states by pairing them up with
+/// colliding blocks. From any leaf, `depth` blocks lead to the same root state.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DiamondStructure {
+    /// Initial state of the hash it was built for, which also fixes the state size
+    initial_state: Vec<u8>,
+    leaves: Vec<Vec<u8>>,
+    /// levels[l][i] takes state i of level l to state i / 2 of level l + 1
+    levels: Vec<Vec<Vec<u8>>>,
+    root: Vec<u8>,
+}
+
+impl DiamondStructure {
+    /// About 2^depth * 2^(b/2 + 1) compression function calls for a b-bit state
+    pub fn build(hash: &MdHash, depth: usize) -> DiamondStructure {
+        let mut states: Vec<Vec<u8>> = Vec::with_capacity(1 << depth);
+        while states.len() < 1 << depth {
+            let state = util::generate_random_bytes(hash.state_bytes());
+            if !states.contains(&state) {
+                states.push(state);
+            }
+        }
+        let leaves = states.clone();
+
+        let mut levels = Vec::with_capacity(depth);
+        while states.len() > 1 {
+            let mut blocks = Vec::with_capacity(states.len());
+            let mut next_states = Vec::with_capacity(states.len() / 2);
+            for pair in states.chunks(2) {
+                let collision = mdhash::find_cross_collision(hash, &pair[0], &pair[1]);
+                let [block_a, block_b] = collision.blocks;
+                blocks.push(block_a);
+                blocks.push(block_b);
+                next_states.push(collision.next_state);
+            }
+            levels.push(blocks);
+            states = next_states;
+        }
+
+        DiamondStructure { initial_state: hash.initial_state().to_vec(), leaves, levels, root: states.remove(0) }
+    }
+
+    pub fn depth(&self) -> usize {
+        self.levels.len()
+    }
+
+    pub fn root(&self) -> &[u8] {
+        &self.root
+    }
+
+    /// The hash committed to in advance: herded messages have `prefix_blocks` blocks of chosen prefix, one
+    /// linking block and `depth` blocks through the diamond, followed by the usual padding
+    pub fn predicted_hash(&self, hash: &MdHash, prefix_blocks: usize) -> Vec<u8> {
+        let message_len = (prefix_blocks + 1 + self.depth()) * BLOCK_SIZE;
+        let padding = mdhash::md_pad(&vec![0; message_len]).split_off(message_len);
+        hash.iterate(&self.root, &padding)
+    }
+
+    /// Blocks leading from leaf `leaf` to the root
+    fn path(&self, mut leaf: usize) -> Vec<u8> {
+        let mut path = Vec::with_capacity(self.depth() * BLOCK_SIZE);
+        for blocks in &self.levels {
+            path.extend_from_slice(&blocks[leaf]);
+            leaf /= 2;
+        }
+        path
+    }
+
+    /// Message starting with `prefix` (padded with spaces to whole blocks) that hashes to
+    /// `predicted_hash` for that many prefix blocks. The linking block search takes about 2^(b - depth) calls.
+    pub fn herd(&self, hash: &MdHash, prefix: &[u8]) -> Vec<u8> {
+        assert_eq!(&self.initial_state[..], hash.initial_state(), "diamond structure built for another hash");
+        let mut message = prefix.to_vec();
+        while !message.len().is_multiple_of(BLOCK_SIZE) {
+            message.push(PREFIX_FILLER);
+        }
+        let state = hash.iterate(hash.initial_state(), &message);
+
+        let leaves: HashMap<&[u8], usize> = self.leaves.iter()
+            .enumerate()
+            .map(|(i, leaf)| (&leaf[..], i))
+            .collect();
+        let (link, leaf) = loop {
+            let link = util::generate_random_bytes(BLOCK_SIZE);
+            if let Some(&leaf) = leaves.get(&hash.compress(&state, &link)[..]) {
+                break (link, leaf);
+            }
+        };
+
+        message.extend_from_slice(&link);
+        message.extend_from_slice(&self.path(leaf));
+        message
+    }
+
+    /// Text format: the state size, the initial state and the depth of the hash it was built for, then the
+    /// leaf states, the root and the blocks of every level, all hex one per line
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let mut f = BufWriter::new(File::create(path)?);
+        writeln!(f, "{}", self.initial_state.len())?;
+        writeln!(f, "{}", util::binary_to_hex(&self.initial_state))?;
+        writeln!(f, "{}", self.depth())?;
+        for state in self.leaves.iter().chain(Some(&self.root)) {
+            writeln!(f, "{}", util::binary_to_hex(state))?;
+        }
+        for message_block in self.levels.iter().flat_map(|blocks| blocks.iter()) {
+            writeln!(f, "{}", util::binary_to_hex(message_block))?;
+        }
+        f.flush()
+    }
+
+    /// Fails with `InvalidData` for a malformed file or one saved for different hash parameters
+    pub fn load(path: &Path, hash: &MdHash) -> io::Result<DiamondStructure> {
+        let f = BufReader::new(File::open(path)?);
+        let mut lines = f.lines();
+        let mut next_line = || -> io::Result<String> {
+            lines.next().unwrap_or_else(|| Err(invalid_data("truncated diamond structure")))
+        };
+        let state_bytes = hash.state_bytes();
+
+        let saved_state_bytes: usize = next_line()?.trim().parse().map_err(|_| invalid_data("bad state size"))?;
+        if saved_state_bytes != state_bytes {
+            return Err(invalid_data("state size does not match the hash"));
+        }
+        if parse_hex(&next_line()?, state_bytes)? != hash.initial_state() {
+            return Err(invalid_data("initial state does not match the hash"));
+        }
+        let depth: usize = next_line()?.trim().parse().map_err(|_| invalid_data("bad depth"))?;
+        if depth > MAX_DEPTH {
+            return Err(invalid_data("depth out of range"));
+        }
+
+        let leaves = (0..1 << depth).map(|_| next_line().and_then(|line| parse_hex(&line, state_bytes)))
+            .collect::<io::Result<Vec<_>>>()?;
+        let root = parse_hex(&next_line()?, state_bytes)?;
+        let levels = (0..depth)
+            .map(|level| {
+                (0..1 << (depth - level)).map(|_| next_line().and_then(|line| parse_hex(&line, BLOCK_SIZE)))
+                    .collect::<io::Result<Vec<_>>>()
+            })
+            .collect::<io::Result<Vec<_>>>()?;
+
+        Ok(DiamondStructure { initial_state: hash.initial_state().to_vec(), leaves, levels, root })
+    }
+}
+
+fn invalid_data(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+/// Hex line that must decode to exactly `len` bytes
+fn parse_hex(line: &str, len: usize) -> io::Result<Vec<u8>> {
+    let line = line.trim();
+    if line.len() != 2 * len {
+        return Err(invalid_data("wrong hex length"));
+    }
+    util::hex_to_binary(line).ok_or_else(|| invalid_data("bad hex"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::fs;
+
+    #[test]
+    fn diamond_structure_works() {
+        let hash = MdHash::with_state_bits(16);
+        let diamond = DiamondStructure::build(&hash, 4);
+        for leaf in 0..16 {
+            assert_eq!(diamond.root(), &hash.iterate(&diamond.leaves[leaf], &diamond.path(leaf))[..]);
+        }
+    }
+
+    #[test]
+    fn herd_works() {
+        let hash = MdHash::with_state_bits(24);
+        let diamond = DiamondStructure::build(&hash, 6);
+        let prediction = diamond.predicted_hash(&hash, 3);
+
+        let prefix = b"Final score: Mariners 4, Yankees 2";
+        let message = diamond.herd(&hash, prefix);
+        assert!(message.starts_with(prefix));
+        assert_eq!(prediction, hash.hash(&message));
+    }
+
+    #[test]
+    fn save_and_load_works() {
+        let hash = MdHash::with_state_bits(16);
+        let diamond = DiamondStructure::build(&hash, 3);
+        let path = env::temp_dir().join(format!("diamond-{}.txt", util::binary_to_hex(&util::generate_random_bytes(8))));
+        diamond.save(&path).unwrap();
+        let loaded = DiamondStructure::load(&path, &hash);
+        let other_size = DiamondStructure::load(&path, &MdHash::with_state_bits(24));
+        let other_initial_state = DiamondStructure::load(&path, &MdHash::new(&[0, 0]));
+        fs::remove_file(&path).unwrap();
+        assert_eq!(diamond, loaded.unwrap());
+        assert_eq!(io::ErrorKind::InvalidData, other_size.unwrap_err().kind());
+        assert_eq!(io::ErrorKind::InvalidData, other_initial_state.unwrap_err().kind());
+    }
+
+    #[test]
+    fn load_rejects_huge_depth() {
+        let hash = MdHash::with_state_bits(16);
+        let path = env::temp_dir().join(format!("diamond-{}.txt", util::binary_to_hex(&util::generate_random_bytes(8))));
+        fs::write(&path, format!("2\n{}\n200\n", util::binary_to_hex(hash.initial_state()))).unwrap();
+        let loaded = DiamondStructure::load(&path, &hash);
+        fs::remove_file(&path).unwrap();
+        assert_eq!(io::ErrorKind::InvalidData, loaded.unwrap_err().kind());
+    }
+}
@@ -0,0 +1,141 @@
+use num_bigint::{BigInt, BigUint, Sign};
+use num_integer::Integer;
+use num_rational::BigRational;
+use num_traits::{One, Signed, Zero};
+
+use ec::{Curve, EcKeyPair, Point};
+use ecdsa;
+use ecdsa::EcdsaSignature;
+use lattice;
+use lattice::Vector;
+use numtheory;
+use util;
+
+pub const DEFAULT_BIAS_BITS: usize = 8;
+
+/// Signs with nonces whose `bias_bits` low bits are always zero, like a broken nonce generator would
+pub struct BiasedSigner {
+    curve: Curve,
+    keypair: EcKeyPair,
+    bias_bits: usize,
+}
+
+impl BiasedSigner {
+    pub fn new(curve: &Curve, bias_bits: usize) -> BiasedSigner {
+        BiasedSigner { curve: curve.clone(), keypair: curve.generate_keypair(), bias_bits }
+    }
+
+    pub fn public_key(&self) -> &Point {
+        &self.keypair.public
+    }
+
+    pub fn sign(&self, msg: &[u8]) -> EcdsaSignature {
+        let bound = (&self.curve.n - 1u32) >> self.bias_bits;
+        loop {
+            let k = (util::generate_random_biguint_below(&bound) + 1u32) << self.bias_bits;
+            if let Some(signature) = ecdsa::sign_with_nonce(&self.curve, self.keypair.private_key(), &k, msg) {
+                return signature;
+            }
+        }
+    }
+
+    pub fn is_private_key(&self, d: &BigUint) -> bool {
+        self.curve.scalar_mul(&self.curve.g, d) == self.keypair.public
+    }
+}
+
+/// Signatures needed for the lattice to pin down the key, with some margin over n.bits() / bias_bits
+pub fn signatures_needed(curve: &Curve, bias_bits: usize) -> usize {
+    (curve.n.bits() as usize).div_ceil(bias_bits) + 4
+}
+
+fn to_rational(n: &BigUint) -> BigRational {
+    BigRational::from_integer(BigInt::from_biguint(Sign::Plus, n.clone()))
+}
+
+/// Hidden number problem: with k = 2^l * b and s = (H + dr) / k, every signature gives d * t - u = b mod n
+/// for a small b < n / 2^l, where t = r / (s * 2^l) and u = H / (-s * 2^l). The lattice spanned by n * e_i,
+/// (t_1 .. t_m, 1 / 2^l, 0) and (u_1 .. u_m, 0, n / 2^l) contains (b_1 .. b_m, d / 2^l, -n / 2^l), which is
+/// short enough for LLL to find.
+pub fn recover_key(curve: &Curve, public: &Point, signed: &[(Vec<u8>, EcdsaSignature)], bias_bits: usize) -> Option<BigUint> {
+    let n = &curve.n;
+    let m = signed.len();
+    let two_l = BigUint::one() << bias_bits;
+
+    let mut ts = Vec::with_capacity(m);
+    let mut us = Vec::with_capacity(m);
+    for (msg, signature) in signed {
+        let inv = numtheory::invmod(&(&signature.s * &two_l % n), n)?;
+        ts.push(&signature.r * &inv % n);
+        us.push((n - ecdsa::hash_message(curve, msg) % n) * &inv % n);
+    }
+
+    let dimension = m + 2;
+    let mut basis: Vec<Vector> = (0..m)
+        .map(|i| {
+            let mut row = vec![BigRational::zero(); dimension];
+            row[i] = to_rational(n);
+            row
+        })
+        .collect();
+    let c_t = BigRational::new(BigInt::one(), BigInt::from_biguint(Sign::Plus, two_l.clone()));
+    let c_u = to_rational(n) * &c_t;
+    let mut t_row: Vector = ts.iter().map(to_rational).collect();
+    t_row.push(c_t.clone());
+    t_row.push(BigRational::zero());
+    let mut u_row: Vector = us.iter().map(to_rational).collect();
+    u_row.push(BigRational::zero());
+    u_row.push(c_u.clone());
+    basis.push(t_row);
+    basis.push(u_row);
+
+    let reduced = lattice::lll(&basis, &lattice::default_delta());
+    let n_int = BigInt::from_biguint(Sign::Plus, n.clone());
+    for row in &reduced {
+        if row[m + 1].abs() != c_u {
+            continue;
+        }
+        // the row is the target vector or its negation
+        let scaled = &row[m] / &c_t;
+        if !scaled.is_integer() {
+            continue;
+        }
+        let mut d = scaled.to_integer();
+        if row[m + 1] == c_u {
+            d = -d;
+        }
+        let d = d.mod_floor(&n_int).to_biguint()?;
+        if curve.scalar_mul(&curve.g, &d) == *public {
+            return Some(d);
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn biased_signatures_verify() {
+        let curve = Curve::cryptopals();
+        let signer = BiasedSigner::new(&curve, DEFAULT_BIAS_BITS);
+        let signature = signer.sign(b"firmware v1.0");
+        assert!(ecdsa::verify(&curve, signer.public_key(), b"firmware v1.0", &signature));
+    }
+
+    #[test]
+    fn recover_key_works() {
+        let curve = Curve::cryptopals();
+        let signer = BiasedSigner::new(&curve, DEFAULT_BIAS_BITS);
+        let signed: Vec<(Vec<u8>, EcdsaSignature)> = (0..signatures_needed(&curve, DEFAULT_BIAS_BITS))
+            .map(|i| {
+                let msg = format!("firmware build {}", i).into_bytes();
+                let signature = signer.sign(&msg);
+                (msg, signature)
+            })
+            .collect();
+        let d = recover_key(&curve, signer.public_key(), &signed, DEFAULT_BIAS_BITS).unwrap();
+        assert!(signer.is_private_key(&d));
+    }
+}
@@ -0,0 +1,173 @@
+use std::cell::Cell;
+
+use num_bigint::BigUint;
+use num_integer::Integer;
+
+use ec;
+use ec::{Curve, EcKeyPair, Point};
+use mac;
+use numtheory;
+use subgroup;
+
+const MAC_MESSAGE: &[u8] = b"crazy flamboyant for the rap enjoyment";
+const FACTOR_BOUND: u64 = 1 << 16;
+
+/// Values of b giving curves with the same p and a as `Curve::cryptopals`, and their orders
+const RELATED_CURVES: [(u32, &str); 3] = [
+    (210, "233970423115425145550826547352470124412"),
+    (504, "233970423115425145544350131142039591210"),
+    (727, "233970423115425145545378039958152057148"),
+];
+
+pub fn mac_with_point(curve: &Curve, shared_secret: &Point, msg: &[u8]) -> Vec<u8> {
+    mac::hmac_sha256(&ec::point_bytes(curve, shared_secret), msg)
+}
+
+/// Bob: answers a public point with a message MACed under the shared point. The vulnerable one multiplies
+/// whatever point it is sent, the validating one refuses points off the curve or outside the subgroup.
+pub struct EcdhResponder {
+    curve: Curve,
+    keypair: EcKeyPair,
+    validate: bool,
+    queries: Cell<u64>,
+}
+
+impl EcdhResponder {
+    pub fn vulnerable(curve: &Curve) -> EcdhResponder {
+        EcdhResponder { curve: curve.clone(), keypair: curve.generate_keypair(), validate: false, queries: Cell::new(0) }
+    }
+
+    pub fn validating(curve: &Curve) -> EcdhResponder {
+        EcdhResponder { validate: true, ..EcdhResponder::vulnerable(curve) }
+    }
+
+    pub fn public_key(&self) -> &Point {
+        &self.keypair.public
+    }
+
+    pub fn queries(&self) -> u64 {
+        self.queries.get()
+    }
+
+    pub fn respond(&self, other_public: &Point) -> Option<(Vec<u8>, Vec<u8>)> {
+        self.queries.set(self.queries.get() + 1);
+        if self.validate && !self.curve.is_valid_public_key(other_public) {
+            return None;
+        }
+        let secret = self.curve.shared_secret(&self.keypair, other_public);
+        Some((MAC_MESSAGE.to_vec(), mac_with_point(&self.curve, &secret, MAC_MESSAGE)))
+    }
+
+    pub fn is_private_key(&self, x: &BigUint) -> bool {
+        self.curve.scalar_mul(&self.curve.g, x) == self.keypair.public
+    }
+}
+
+/// The curve with a different b. Its `g` and `n` are meaningless, but the addition formulas never use b,
+/// so arithmetic on its points works with either curve.
+pub fn related_curve(curve: &Curve, b: BigUint, order: BigUint) -> Curve {
+    Curve { b, order, ..curve.clone() }
+}
+
+/// Point of order r, r a prime factor of the curve order: a random point is pushed into the r-Sylow subgroup
+/// by removing every other factor of the order, then multiplied by r while that stays above infinity. This
+/// also works when the r-part of the group isn't cyclic.
+pub fn point_of_order(curve: &Curve, r: u64) -> Point {
+    let r = BigUint::from(r);
+    let mut cofactor = curve.order.clone();
+    while cofactor.is_multiple_of(&r) {
+        cofactor /= &r;
+    }
+    loop {
+        let mut point = curve.scalar_mul(&curve.random_point(), &cofactor);
+        if point == Point::Infinity {
+            continue;
+        }
+        loop {
+            let next = curve.scalar_mul(&point, &r);
+            if next == Point::Infinity {
+                return point;
+            }
+            point = next;
+        }
+    }
+}
+
+/// x mod r by trying every multiple of the order r point until the MAC matches
+fn brute_force_residue(curve: &Curve, point: &Point, r: u64, msg: &[u8], tag: &[u8]) -> Option<u64> {
+    let mut candidate = Point::Infinity;
+    for b in 0..r {
+        if mac_with_point(curve, &candidate, msg) == tag {
+            return Some(b);
+        }
+        candidate = curve.add(&candidate, point);
+    }
+    None
+}
+
+/// Sends points of small order on the related curves, collects the private key modulo every distinct small
+/// factor of their orders and combines them with CRT. `None` if the responder refuses a point.
+pub fn invalid_curve_attack(curve: &Curve, related: &[Curve], responder: &EcdhResponder) -> Option<BigUint> {
+    let mut residues = Vec::new();
+    let mut moduli: Vec<BigUint> = Vec::new();
+
+    for weak_curve in related {
+        for r in subgroup::small_factors(&weak_curve.order, FACTOR_BOUND) {
+            let r_big = BigUint::from(r);
+            if moduli.contains(&r_big) {
+                continue;
+            }
+            let point = point_of_order(weak_curve, r);
+            let (msg, tag) = responder.respond(&point)?;
+            residues.push(BigUint::from(brute_force_residue(curve, &point, r, &msg, &tag)?));
+            moduli.push(r_big);
+            if moduli.iter().product::<BigUint>() > curve.n {
+                let (x, _) = numtheory::crt(&residues, &moduli)?;
+                return Some(x.mod_floor(&curve.n));
+            }
+        }
+    }
+
+    None
+}
+
+/// `Curve::cryptopals` and the three related curves from `RELATED_CURVES`
+pub fn cryptopals_related_curves() -> (Curve, Vec<Curve>) {
+    let curve = Curve::cryptopals();
+    let related = RELATED_CURVES.iter()
+        .map(|&(b, order)| related_curve(&curve, BigUint::from(b), BigUint::parse_bytes(order.as_bytes(), 10).unwrap()))
+        .collect();
+    (curve, related)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn point_of_order_works() {
+        let (_, related) = cryptopals_related_curves();
+        let point = point_of_order(&related[0], 4999);
+        assert!(related[0].is_on_curve(&point));
+        assert_eq!(Point::Infinity, related[0].scalar_mul(&point, &BigUint::from(4999u32)));
+    }
+
+    #[test]
+    fn invalid_curve_attack_works() {
+        let (curve, related) = cryptopals_related_curves();
+        let responder = EcdhResponder::vulnerable(&curve);
+        let x = invalid_curve_attack(&curve, &related, &responder).unwrap();
+        assert!(responder.is_private_key(&x));
+    }
+
+    #[test]
+    fn validating_responder_stops_attack() {
+        let (curve, related) = cryptopals_related_curves();
+        let responder = EcdhResponder::validating(&curve);
+        assert_eq!(None, invalid_curve_attack(&curve, &related, &responder));
+        assert_eq!(1, responder.queries());
+
+        let other = curve.generate_keypair();
+        assert!(responder.respond(&other.public).is_some());
+    }
+}
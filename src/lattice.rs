@@ -0,0 +1,154 @@
+use num_bigint::BigInt;
+use num_rational::BigRational;
+use num_traits::{One, Signed, Zero};
+
+/// Lattice basis vector with exact rational coordinates
+pub type Vector = Vec<BigRational>;
+
+pub fn dot(a: &[BigRational], b: &[BigRational]) -> BigRational {
+    a.iter().zip(b).fold(BigRational::zero(), |acc, (x, y)| acc + x * y)
+}
+
+/// a - c * b
+fn sub_scaled(a: &[BigRational], b: &[BigRational], c: &BigRational) -> Vector {
+    a.iter().zip(b).map(|(x, y)| x - c * y).collect()
+}
+
+/// Gram-Schmidt coefficients of `basis`: mu[i][j] = <b_i, b*_j> / <b*_j, b*_j> for j < i, and the squared
+/// norms <b*_i, b*_i> of the orthogonalized vectors
+fn gram_schmidt(basis: &[Vector]) -> (Vec<Vec<BigRational>>, Vec<BigRational>) {
+    let n = basis.len();
+    let mut orthogonal: Vec<Vector> = Vec::with_capacity(n);
+    let mut mu = vec![vec![BigRational::zero(); n]; n];
+    let mut norms = Vec::with_capacity(n);
+    for i in 0..n {
+        let mut b_star = basis[i].clone();
+        for j in 0..i {
+            mu[i][j] = if norms[j] == BigRational::zero() {
+                BigRational::zero()
+            } else {
+                dot(&basis[i], &orthogonal[j]) / &norms[j]
+            };
+            b_star = sub_scaled(&b_star, &orthogonal[j], &mu[i][j]);
+        }
+        norms.push(dot(&b_star, &b_star));
+        orthogonal.push(b_star);
+    }
+    (mu, norms)
+}
+
+/// State of the reduction: the basis with its Gram-Schmidt data, updated in place instead of recomputed
+struct Reduction {
+    basis: Vec<Vector>,
+    mu: Vec<Vec<BigRational>>,
+    norms: Vec<BigRational>,
+}
+
+impl Reduction {
+    /// Makes |mu[k][l]| <= 1/2 by subtracting the nearest integer multiple of b_l from b_k
+    fn size_reduce(&mut self, k: usize, l: usize) {
+        let half = BigRational::new(BigInt::one(), BigInt::from(2));
+        if self.mu[k][l].abs() <= half {
+            return;
+        }
+        let q = self.mu[k][l].round();
+        self.basis[k] = sub_scaled(&self.basis[k], &self.basis[l], &q);
+        self.mu[k][l] = &self.mu[k][l] - &q;
+        for j in 0..l {
+            let delta = &q * &self.mu[l][j];
+            self.mu[k][j] = &self.mu[k][j] - delta;
+        }
+    }
+
+    /// Exchanges b_k and b_{k-1}, only rows k - 1 and k and columns k - 1 and k of mu change
+    fn swap(&mut self, k: usize) {
+        let m = self.mu[k][k - 1].clone();
+        let norm = &self.norms[k] + &m * &m * &self.norms[k - 1];
+        if norm.is_zero() {
+            self.basis.swap(k, k - 1);
+            return;
+        }
+        self.mu[k][k - 1] = &m * &self.norms[k - 1] / &norm;
+        self.norms[k] = &self.norms[k - 1] * &self.norms[k] / &norm;
+        self.norms[k - 1] = norm;
+        self.basis.swap(k, k - 1);
+        for j in 0..k - 1 {
+            let t = self.mu[k][j].clone();
+            self.mu[k][j] = self.mu[k - 1][j].clone();
+            self.mu[k - 1][j] = t;
+        }
+        for i in k + 1..self.basis.len() {
+            let t = self.mu[i][k].clone();
+            self.mu[i][k] = &self.mu[i][k - 1] - &m * &t;
+            self.mu[i][k - 1] = t + &self.mu[k][k - 1] * &self.mu[i][k];
+        }
+    }
+}
+
+/// Lenstra-Lenstra-Lovász reduction: the same lattice spanned by short, nearly orthogonal vectors. `delta` in
+/// (1/4, 1) trades running time for quality, 99/100 is the usual choice.
+pub fn lll(basis: &[Vector], delta: &BigRational) -> Vec<Vector> {
+    let (mu, norms) = gram_schmidt(basis);
+    let mut state = Reduction { basis: basis.to_vec(), mu, norms };
+    let n = basis.len();
+    let mut k = 1;
+    while k < n {
+        state.size_reduce(k, k - 1);
+        let m = &state.mu[k][k - 1];
+        // Lovász condition
+        if state.norms[k] >= (delta - m * m) * &state.norms[k - 1] {
+            for l in (0..k - 1).rev() {
+                state.size_reduce(k, l);
+            }
+            k += 1;
+        } else {
+            state.swap(k);
+            k = if k > 1 { k - 1 } else { 1 };
+        }
+    }
+    state.basis
+}
+
+pub fn default_delta() -> BigRational {
+    BigRational::new(BigInt::from(99), BigInt::from(100))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vector(entries: &[(i64, i64)]) -> Vector {
+        entries.iter().map(|&(n, d)| BigRational::new(BigInt::from(n), BigInt::from(d))).collect()
+    }
+
+    #[test]
+    fn lll_works() {
+        let basis = vec![
+            vector(&[(-2, 1), (0, 1), (2, 1), (0, 1)]),
+            vector(&[(1, 2), (-1, 1), (0, 1), (0, 1)]),
+            vector(&[(-1, 1), (0, 1), (-2, 1), (1, 2)]),
+            vector(&[(-1, 1), (1, 1), (1, 1), (2, 1)]),
+        ];
+        let expected = vec![
+            vector(&[(1, 2), (-1, 1), (0, 1), (0, 1)]),
+            vector(&[(-1, 1), (0, 1), (-2, 1), (1, 2)]),
+            vector(&[(-1, 2), (0, 1), (1, 1), (2, 1)]),
+            vector(&[(-3, 2), (-1, 1), (2, 1), (0, 1)]),
+        ];
+        assert_eq!(expected, lll(&basis, &default_delta()));
+    }
+
+    #[test]
+    fn lll_keeps_gram_schmidt_consistent() {
+        let basis: Vec<Vector> = (0..6)
+            .map(|i| (0..6).map(|j| BigRational::from_integer(BigInt::from((i * 7 + j * 13) % 11 - 5 + if i == j { 20 } else { 0 }))).collect())
+            .collect();
+        let reduced = lll(&basis, &default_delta());
+        let (mu, norms) = gram_schmidt(&reduced);
+        let half = BigRational::new(BigInt::one(), BigInt::from(2));
+        for k in 1..reduced.len() {
+            assert!(mu[k][..k].iter().all(|m| m.abs() <= half));
+            assert!(norms[k] >= (default_delta() - &mu[k][k - 1] * &mu[k][k - 1]) * &norms[k - 1]);
+        }
+    }
+}
@@ -1,7 +1,38 @@
 extern crate openssl;
 extern crate rand;
+extern crate num_bigint;
+extern crate num_integer;
+extern crate num_traits;
+extern crate num_rational;
+extern crate miniz_oxide;
 
 pub mod base64;
 pub mod util;
 pub mod xor;
-pub mod block;
\ No newline at end of file
+pub mod block;
+pub mod dh;
+pub mod mitm;
+pub mod mac;
+pub mod srp;
+pub mod numtheory;
+pub mod rsa;
+pub mod pkcs1;
+pub mod dsa;
+pub mod crime;
+pub mod mdhash;
+pub mod herding;
+pub mod rc4;
+pub mod subgroup;
+pub mod dlog;
+pub mod ec;
+pub mod invalidcurve;
+pub mod montgomery;
+pub mod twist;
+pub mod ecdsa;
+pub mod dsks;
+pub mod lattice;
+pub mod hnp;
+pub mod gf128;
+pub mod gcm;
+pub mod bitmatrix;
+pub mod truncgcm;
\ No newline at end of file
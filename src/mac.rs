@@ -0,0 +1,321 @@
+use openssl::sha;
+
+use block;
+use block::{BLOCK_SIZE, Padding};
+use util;
+
+const SHA256_BLOCK_SIZE: usize = 64;
+const CMAC_RB: u8 = 0x87;
+
+pub fn hmac_sha256(key: &[u8], msg: &[u8]) -> Vec<u8> {
+    let mut block_key = if key.len() > SHA256_BLOCK_SIZE {
+        sha::sha256(key).to_vec()
+    } else {
+        key.to_vec()
+    };
+    block_key.resize(SHA256_BLOCK_SIZE, 0);
+
+    let mut inner = sha::Sha256::new();
+    inner.update(&block_key.iter().map(|b| b ^ 0x36).collect::<Vec<u8>>());
+    inner.update(msg);
+    let inner_hash = inner.finish();
+
+    let mut outer = sha::Sha256::new();
+    outer.update(&block_key.iter().map(|b| b ^ 0x5c).collect::<Vec<u8>>());
+    outer.update(&inner_hash);
+    outer.finish().to_vec()
+}
+
+/// Last block of the AES-CBC encryption of the PKCS#7 padded message
+pub fn cbc_mac(key: &[u8], msg: &[u8], iv: &[u8]) -> Vec<u8> {
+    let encrypted = block::aes_cbc_encrypt(key, msg, iv, Padding::PKCS7).unwrap();
+    encrypted[(encrypted.len() - BLOCK_SIZE)..].to_vec()
+}
+
+fn cmac_double(block: &[u8]) -> Vec<u8> {
+    let mut doubled = vec![0u8; BLOCK_SIZE];
+    for i in 0..BLOCK_SIZE {
+        let carry = if i + 1 < BLOCK_SIZE { block[i + 1] >> 7 } else { 0 };
+        doubled[i] = (block[i] << 1) | carry;
+    }
+    if block[0] & 0x80 != 0 {
+        doubled[BLOCK_SIZE - 1] ^= CMAC_RB;
+    }
+    doubled
+}
+
+/// AES-CMAC (RFC 4493), CBC-MAC with the last block masked by a key-derived subkey,
+/// which rules out the splicing and IV forgeries possible against `cbc_mac`
+pub fn cmac(key: &[u8], msg: &[u8]) -> Vec<u8> {
+    let l = block::aes_ecb_encrypt(key, &[0u8; BLOCK_SIZE], Padding::NoPadding).unwrap();
+    let k1 = cmac_double(&l);
+    let k2 = cmac_double(&k1);
+
+    let mut data = msg.to_vec();
+    let last_start = if data.is_empty() { 0 } else { (data.len() - 1) / BLOCK_SIZE * BLOCK_SIZE };
+    let subkey = if !data.is_empty() && data.len().is_multiple_of(BLOCK_SIZE) {
+        k1
+    } else {
+        data.push(0x80);
+        data.resize(last_start + BLOCK_SIZE, 0);
+        k2
+    };
+    let masked = util::xor_bufs(&data[last_start..], &subkey);
+    data.truncate(last_start);
+    data.extend_from_slice(&masked);
+
+    let encrypted = block::aes_cbc_encrypt(key, &data, &[0u8; BLOCK_SIZE], Padding::NoPadding).unwrap();
+    encrypted[(encrypted.len() - BLOCK_SIZE)..].to_vec()
+}
+
+#[derive(PartialEq, Debug)]
+pub struct Transfer {
+    pub from: u32,
+    pub to: u32,
+    pub amount: u64,
+}
+
+#[derive(PartialEq, Debug)]
+pub struct MultiTransfer {
+    pub from: u32,
+    pub transfers: Vec<(u32, u64)>,
+}
+
+fn parse_number<T: ::std::str::FromStr>(bytes: &[u8]) -> Option<T> {
+    ::std::str::from_utf8(bytes).ok()?.parse().ok()
+}
+
+/// Splits `a=1&b=2` style bytes, keeping pairs that contain garbage as long as they have a '='
+fn parse_params(msg: &[u8]) -> Vec<(&[u8], &[u8])> {
+    msg.split(|&b| b == b'&')
+        .filter_map(|pair| {
+            let eq = pair.iter().position(|&b| b == b'=')?;
+            Some((&pair[..eq], &pair[(eq + 1)..]))
+        })
+        .collect()
+}
+
+fn param<'a>(params: &[(&[u8], &'a [u8])], name: &[u8]) -> Option<&'a [u8]> {
+    params.iter().find(|&&(key, _)| key == name).map(|&(_, value)| value)
+}
+
+/// Server sharing a key with its clients. Single transfers are authenticated as
+/// message || IV || MAC with a client-chosen IV, multi-transfers as message || MAC with a zero IV.
+pub struct TransferServer {
+    key: Vec<u8>,
+}
+
+impl Default for TransferServer {
+    fn default() -> TransferServer {
+        TransferServer::new()
+    }
+}
+
+impl TransferServer {
+    pub fn new() -> TransferServer {
+        TransferServer { key: util::generate_random_bytes(BLOCK_SIZE) }
+    }
+
+    /// What a legitimate client holding the key sends: `from=#{from}&to=#{to}&amount=#{amount}`
+    pub fn sign_request(&self, from: u32, to: u32, amount: u64) -> Vec<u8> {
+        let mut request = format!("from={}&to={}&amount={}", from, to, amount).into_bytes();
+        let iv = util::generate_random_bytes(BLOCK_SIZE);
+        let mac = cbc_mac(&self.key, &request, &iv);
+        request.extend_from_slice(&iv);
+        request.extend_from_slice(&mac);
+        request
+    }
+
+    pub fn process_request(&self, request: &[u8]) -> Option<Transfer> {
+        if request.len() < 2 * BLOCK_SIZE {
+            return None;
+        }
+        let (msg, rest) = request.split_at(request.len() - 2 * BLOCK_SIZE);
+        let (iv, mac) = rest.split_at(BLOCK_SIZE);
+        if cbc_mac(&self.key, msg, iv)[..] != *mac {
+            return None;
+        }
+
+        let params = parse_params(msg);
+        Some(Transfer {
+            from: parse_number(param(&params, b"from")?)?,
+            to: parse_number(param(&params, b"to")?)?,
+            amount: parse_number(param(&params, b"amount")?)?,
+        })
+    }
+
+    /// `from=#{from}&tx_list=#{to:amount(;to:amount)*}`
+    pub fn sign_multi_request(&self, from: u32, transfers: &[(u32, u64)]) -> Vec<u8> {
+        let tx_list: Vec<String> = transfers.iter().map(|&(to, amount)| format!("{}:{}", to, amount)).collect();
+        let mut request = format!("from={}&tx_list={}", from, tx_list.join(";")).into_bytes();
+        let mac = cbc_mac(&self.key, &request, &[0u8; BLOCK_SIZE]);
+        request.extend_from_slice(&mac);
+        request
+    }
+
+    pub fn process_multi_request(&self, request: &[u8]) -> Option<MultiTransfer> {
+        if request.len() < BLOCK_SIZE {
+            return None;
+        }
+        let (msg, mac) = request.split_at(request.len() - BLOCK_SIZE);
+        if cbc_mac(&self.key, msg, &[0u8; BLOCK_SIZE])[..] != *mac {
+            return None;
+        }
+
+        let params = parse_params(msg);
+        let transfers = param(&params, b"tx_list")?
+            .split(|&b| b == b';')
+            .filter_map(|tx| {
+                let colon = tx.iter().position(|&b| b == b':')?;
+                Some((parse_number(&tx[..colon])?, parse_number(&tx[(colon + 1)..])?))
+            })
+            .collect();
+        Some(MultiTransfer { from: parse_number(param(&params, b"from")?)?, transfers })
+    }
+}
+
+/// Rewrites `from=` in the first block of an attacker's own signed request and fixes up the IV
+/// so the MAC still verifies. The account ids need the same number of digits.
+pub fn forge_transfer_iv(request: &[u8], victim: u32) -> Option<Vec<u8>> {
+    if request.len() < 3 * BLOCK_SIZE {
+        return None;
+    }
+    let msg_len = request.len() - 2 * BLOCK_SIZE;
+    let first_block = &request[..BLOCK_SIZE];
+    let from_end = first_block.iter().position(|&b| b == b'&')?;
+
+    let mut forged_block = format!("from={}", victim).into_bytes();
+    if !first_block.starts_with(b"from=") || forged_block.len() != from_end {
+        return None;
+    }
+    forged_block.extend_from_slice(&first_block[from_end..]);
+
+    let iv = &request[msg_len..(msg_len + BLOCK_SIZE)];
+    let forged_iv = util::xor_bufs(&util::xor_bufs(iv, first_block), &forged_block);
+
+    let mut forged = forged_block;
+    forged.extend_from_slice(&request[BLOCK_SIZE..msg_len]);
+    forged.extend_from_slice(&forged_iv);
+    forged.extend_from_slice(&request[(msg_len + BLOCK_SIZE)..]);
+    Some(forged)
+}
+
+/// Length extension of a captured zero-IV multi-transfer: pad(M) || (M2[0] ^ t) || M2[1..] has the
+/// attacker's MAC t2, so the transfers in M2's later blocks get appended to the victim's request.
+/// `None` when a request is too short or the glue block contains a separator that would cut the
+/// transfer list short, in which case the attacker waits for another captured request.
+pub fn forge_transfer_extension(captured: &[u8], attacker_request: &[u8]) -> Option<Vec<u8>> {
+    if captured.len() < BLOCK_SIZE || attacker_request.len() < 2 * BLOCK_SIZE {
+        return None;
+    }
+    let (msg, mac) = captured.split_at(captured.len() - BLOCK_SIZE);
+    let (attacker_msg, attacker_mac) = attacker_request.split_at(attacker_request.len() - BLOCK_SIZE);
+
+    let glue = util::xor_bufs(&attacker_msg[..BLOCK_SIZE], mac);
+    if glue.iter().any(|&b| b == b'&' || b == b';') {
+        return None;
+    }
+    let mut forged = block::pad_pkcs(msg.to_vec(), BLOCK_SIZE);
+    forged.extend_from_slice(&glue);
+    forged.extend_from_slice(&attacker_msg[BLOCK_SIZE..]);
+    forged.extend_from_slice(attacker_mac);
+    Some(forged)
+}
+
+/// Makes `prefix` (padded with `filler` to a block boundary) || glue block || original[16..] which has
+/// the same zero-IV CBC-MAC as `original`
+pub fn forge_cbc_mac_collision(key: &[u8], original: &[u8], prefix: &[u8], filler: u8) -> Vec<u8> {
+    assert!(original.len() >= BLOCK_SIZE);
+    let mut forged = prefix.to_vec();
+    while !forged.len().is_multiple_of(BLOCK_SIZE) {
+        forged.push(filler);
+    }
+
+    let state = block::aes_cbc_encrypt(key, &forged, &[0u8; BLOCK_SIZE], Padding::NoPadding).unwrap();
+    let state = &state[(state.len() - BLOCK_SIZE)..];
+    forged.extend_from_slice(&util::xor_bufs(state, &original[..BLOCK_SIZE]));
+    forged.extend_from_slice(&original[BLOCK_SIZE..]);
+    forged
+}
+
+/// CBC-MAC collision for a JavaScript snippet used as a hash: the new code is followed by a `//`
+/// comment hiding the glue block, retried with more padding until the glue has no line breaks
+pub fn forge_js_collision(key: &[u8], original: &[u8], js_code: &[u8]) -> Vec<u8> {
+    let mut prefix = js_code.to_vec();
+    prefix.extend_from_slice(b"//");
+    loop {
+        let forged = forge_cbc_mac_collision(key, original, &prefix, b' ');
+        let comment_start = js_code.len() + 2;
+        let line_end = forged.len() - if original.ends_with(b"\n") { 1 } else { 0 };
+        if !forged[comment_start..line_end].iter().any(|&b| b == b'\n' || b == b'\r') {
+            return forged;
+        }
+        prefix.push(b' ');
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use util::*;
+
+    #[test]
+    fn hmac_sha256_works() {
+        // RFC 4231, test cases 2 and 6
+        assert_eq!(hex_to_binary("5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843").unwrap(),
+                    hmac_sha256(b"Jefe", b"what do ya want for nothing?"));
+        assert_eq!(hex_to_binary("60e431591ee0b67f0d8a26aacbf5b77f8e0bc6213728c5140546040f0ee37f54").unwrap(),
+                    hmac_sha256(&[0xaa; 131], b"Test Using Larger Than Block-Size Key - Hash Key First"));
+    }
+
+    #[test]
+    fn cmac_works() {
+        // RFC 4493, examples 1, 2 and 4
+        let key = hex_to_binary("2b7e151628aed2a6abf7158809cf4f3c").unwrap();
+        let msg = hex_to_binary("6bc1bee22e409f96e93d7e117393172aae2d8a571e03ac9c9eb76fac45af8e5130c81c46a35ce411e5fbc1191a0a52eff69f2445df4f9b17ad2b417be66c3710").unwrap();
+        assert_eq!(hex_to_binary("bb1d6929e95937287fa37d129b756746").unwrap(), cmac(&key, &[]));
+        assert_eq!(hex_to_binary("070a16b46b4d4144f79bdd9dd04a287c").unwrap(), cmac(&key, &msg[..16]));
+        assert_eq!(hex_to_binary("51f0bebf7e3b9d92fc49741779363cfe").unwrap(), cmac(&key, &msg));
+    }
+
+    #[test]
+    fn forge_transfer_iv_works() {
+        let server = TransferServer::new();
+        let request = server.sign_request(1337, 1337, 1000000);
+        assert_eq!(Some(Transfer { from: 1337, to: 1337, amount: 1000000 }), server.process_request(&request));
+
+        let forged = forge_transfer_iv(&request, 4242).unwrap();
+        assert_eq!(Some(Transfer { from: 4242, to: 1337, amount: 1000000 }), server.process_request(&forged));
+        assert_eq!(None, forge_transfer_iv(&request, 42));
+    }
+
+    #[test]
+    fn forge_transfer_extension_works() {
+        let server = TransferServer::new();
+        let attacker_request = server.sign_multi_request(1337, &[(1337, 1), (1337, 1000000)]);
+        // the glue block depends on the victim's MAC, so wait for another of their requests if it has a separator
+        let (captured, forged) = (250..)
+            .filter_map(|amount| {
+                let captured = server.sign_multi_request(4242, &[(17, 100), (23, amount)]);
+                forge_transfer_extension(&captured, &attacker_request).map(|forged| (captured, forged))
+            })
+            .next()
+            .unwrap();
+        let transfer = server.process_multi_request(&forged).unwrap();
+        assert_eq!(4242, transfer.from);
+        assert_eq!(Some(&(1337, 1000000)), transfer.transfers.last());
+        assert_eq!(None, forge_transfer_extension(&captured, &captured[..BLOCK_SIZE]));
+    }
+
+    #[test]
+    fn forge_js_collision_works() {
+        let key = b"YELLOW SUBMARINE";
+        let original = b"alert('MZA who was that?');\n";
+        assert_eq!(hex_to_binary("296b8d7cb78a243dda4d0a61d33bbdd1").unwrap(), cbc_mac(key, original, &[0u8; BLOCK_SIZE]));
+
+        let forged = forge_js_collision(key, original, b"alert('Ayo, the Wu is back!');");
+        assert!(forged.starts_with(b"alert('Ayo, the Wu is back!');//"));
+        assert_eq!(cbc_mac(key, original, &[0u8; BLOCK_SIZE]), cbc_mac(key, &forged, &[0u8; BLOCK_SIZE]));
+        assert!(!forged[..(forged.len() - 1)].iter().any(|&b| b == b'\n' || b == b'\r'));
+    }
+}
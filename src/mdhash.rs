@@ -0,0 +1,424 @@
+use std::cell::Cell;
+use std::collections::HashMap;
+
+use block;
+use block::BLOCK_SIZE;
+use util;
+
+pub const MIN_STATE_BYTES: usize = 2;
+pub const MAX_STATE_BYTES: usize = 4;
+
+/// Toy Merkle-Damgård hash: the compression function encrypts the message block with AES keyed by the
+/// (zero padded) state and keeps only the first `state_bytes` of the result. Every compression function
+/// call is counted, so attacks can report how much work they did.
+pub struct MdHash {
+    initial_state: Vec<u8>,
+    compressions: Cell<u64>,
+}
+
+impl MdHash {
+    pub fn new(initial_state: &[u8]) -> MdHash {
+        assert!(initial_state.len() >= MIN_STATE_BYTES && initial_state.len() <= MAX_STATE_BYTES);
+        MdHash { initial_state: initial_state.to_vec(), compressions: Cell::new(0) }
+    }
+
+    /// Hash with a `state_bits` (16, 24 or 32) state and an arbitrary fixed initial state
+    pub fn with_state_bits(state_bits: usize) -> MdHash {
+        assert!(state_bits.is_multiple_of(8));
+        let initial_state: Vec<u8> = (0..state_bits / 8).map(|i| 0x10 * (i as u8 + 1)).collect();
+        MdHash::new(&initial_state)
+    }
+
+    pub fn state_bytes(&self) -> usize {
+        self.initial_state.len()
+    }
+
+    pub fn initial_state(&self) -> &[u8] {
+        &self.initial_state
+    }
+
+    pub fn compressions(&self) -> u64 {
+        self.compressions.get()
+    }
+
+    pub fn reset_compressions(&self) {
+        self.compressions.set(0);
+    }
+
+    pub fn compress(&self, state: &[u8], message_block: &[u8]) -> Vec<u8> {
+        assert_eq!(self.state_bytes(), state.len());
+        self.compressions.set(self.compressions.get() + 1);
+        let mut key = state.to_vec();
+        key.resize(BLOCK_SIZE, 0);
+        let mut encrypted = block::aes_encrypt_block(&key, message_block).unwrap();
+        encrypted.truncate(self.state_bytes());
+        encrypted
+    }
+
+    /// Runs the compression function over whole blocks, without any padding
+    pub fn iterate(&self, state: &[u8], blocks: &[u8]) -> Vec<u8> {
+        assert!(blocks.len().is_multiple_of(BLOCK_SIZE));
+        blocks.chunks(BLOCK_SIZE)
+            .fold(state.to_vec(), |state, message_block| self.compress(&state, message_block))
+    }
+
+    /// Full hash with Merkle-Damgård strengthening: 0x80, zeros and the message bit length
+    pub fn hash(&self, message: &[u8]) -> Vec<u8> {
+        self.iterate(&self.initial_state, &md_pad(message))
+    }
+}
+
+pub fn md_pad(message: &[u8]) -> Vec<u8> {
+    let mut padded = message.to_vec();
+    padded.push(0x80);
+    while padded.len() % BLOCK_SIZE != BLOCK_SIZE - 8 {
+        padded.push(0);
+    }
+    padded.extend_from_slice(&(message.len() as u64 * 8).to_be_bytes());
+    padded
+}
+
+/// Two different blocks that take the same state to `next_state`
+#[derive(Clone, Debug)]
+pub struct Collision {
+    pub blocks: [Vec<u8>; 2],
+    pub next_state: Vec<u8>,
+    pub compressions: u64,
+}
+
+/// Birthday search, about 2^(b/2) compression function calls for a b-bit state
+pub fn find_collision(hash: &MdHash, state: &[u8]) -> Collision {
+    let start = hash.compressions();
+    let mut seen: HashMap<Vec<u8>, Vec<u8>> = HashMap::new();
+    loop {
+        let message_block = util::generate_random_bytes(BLOCK_SIZE);
+        let next_state = hash.compress(state, &message_block);
+        match seen.get(&next_state) {
+            Some(other) if *other != message_block => {
+                return Collision {
+                    blocks: [other.clone(), message_block],
+                    next_state,
+                    compressions: hash.compressions() - start,
+                };
+            }
+            _ => {
+                seen.insert(next_state, message_block);
+            }
+        }
+    }
+}
+
+/// Chain of n collisions: any choice of one block from every stage gives the same final state, so it
+/// describes 2^n colliding messages of n blocks each
+#[derive(Clone, Debug)]
+pub struct MultiCollision {
+    pub stages: Vec<Collision>,
+}
+
+impl MultiCollision {
+    pub fn final_state(&self) -> Option<&[u8]> {
+        self.stages.last().map(|stage| &stage.next_state[..])
+    }
+
+    pub fn compressions(&self) -> u64 {
+        self.stages.iter().map(|stage| stage.compressions).sum()
+    }
+
+    pub fn message_count(&self) -> usize {
+        1 << self.stages.len()
+    }
+
+    /// The message selected by the bits of `index`, the first stage being the most significant bit
+    pub fn message(&self, index: usize) -> Vec<u8> {
+        let n = self.stages.len();
+        self.stages.iter()
+            .enumerate()
+            .flat_map(|(i, stage)| stage.blocks[(index >> (n - 1 - i)) & 1].iter().cloned())
+            .collect()
+    }
+
+    pub fn messages(&self) -> Vec<Vec<u8>> {
+        (0..self.message_count()).map(|index| self.message(index)).collect()
+    }
+}
+
+/// Joux: n successive birthday searches, n * 2^(b/2) work instead of 2^(b * (2^n - 1) / 2^n)
+pub fn joux_multicollision(hash: &MdHash, state: &[u8], n: usize) -> MultiCollision {
+    let mut multicollision = MultiCollision { stages: Vec::with_capacity(n) };
+    extend_multicollision(hash, &mut multicollision, state, n);
+    multicollision
+}
+
+fn extend_multicollision(hash: &MdHash, multicollision: &mut MultiCollision, initial_state: &[u8], stages: usize) {
+    for _ in 0..stages {
+        let state = multicollision.final_state().unwrap_or(initial_state).to_vec();
+        multicollision.stages.push(find_collision(hash, &state));
+    }
+}
+
+#[derive(Debug)]
+pub struct ConcatenatedCollision {
+    pub messages: [Vec<u8>; 2],
+    /// Number of Joux stages the cheap hash multicollision ended up with
+    pub stages: usize,
+    pub cheap_compressions: u64,
+    pub strong_compressions: u64,
+}
+
+/// Collision in cheap(m) || strong(m): generate 2^(b2/2) cheap hash collisions and look for a pair that
+/// collides in the strong hash too, adding stages until one does. The total is roughly the cost of
+/// attacking the strong hash alone.
+pub fn concatenated_hash_collision(cheap: &MdHash, strong: &MdHash) -> ConcatenatedCollision {
+    let cheap_start = cheap.compressions();
+    let strong_start = strong.compressions();
+    let mut multicollision = MultiCollision { stages: Vec::new() };
+    extend_multicollision(cheap, &mut multicollision, cheap.initial_state(), strong.state_bytes() * 4);
+
+    loop {
+        let mut seen: HashMap<Vec<u8>, usize> = HashMap::new();
+        for index in 0..multicollision.message_count() {
+            let message = multicollision.message(index);
+            let digest = strong.hash(&message);
+            if let Some(&other) = seen.get(&digest) {
+                return ConcatenatedCollision {
+                    messages: [multicollision.message(other), message],
+                    stages: multicollision.stages.len(),
+                    cheap_compressions: cheap.compressions() - cheap_start,
+                    strong_compressions: strong.compressions() - strong_start,
+                };
+            }
+            seen.insert(digest, index);
+        }
+        extend_multicollision(cheap, &mut multicollision, cheap.initial_state(), 1);
+    }
+}
+
+/// Blocks `[a, b]` with compress(state_a, a) = compress(state_b, b) = `next_state`
+#[derive(Clone, Debug)]
+pub struct CrossCollision {
+    pub blocks: [Vec<u8>; 2],
+    pub next_state: Vec<u8>,
+}
+
+/// Birthday search between two different starting states
+pub fn find_cross_collision(hash: &MdHash, state_a: &[u8], state_b: &[u8]) -> CrossCollision {
+    let mut seen_a: HashMap<Vec<u8>, Vec<u8>> = HashMap::new();
+    let mut seen_b: HashMap<Vec<u8>, Vec<u8>> = HashMap::new();
+    loop {
+        let block_a = util::generate_random_bytes(BLOCK_SIZE);
+        let next_a = hash.compress(state_a, &block_a);
+        if let Some(block_b) = seen_b.get(&next_a) {
+            return CrossCollision { blocks: [block_a, block_b.clone()], next_state: next_a };
+        }
+        seen_a.insert(next_a, block_a);
+
+        let block_b = util::generate_random_bytes(BLOCK_SIZE);
+        let next_b = hash.compress(state_b, &block_b);
+        if let Some(block_a) = seen_a.get(&next_b) {
+            return CrossCollision { blocks: [block_a.clone(), block_b], next_state: next_b };
+        }
+        seen_b.insert(next_b, block_b);
+    }
+}
+
+/// One stage of an expandable message: a single block and 2^j + 1 blocks leading to the same state
+#[derive(Clone, Debug)]
+pub struct ExpandableStage {
+    pub short: Vec<u8>,
+    pub long: Vec<u8>,
+}
+
+/// (k, k + 2^k - 1)-expandable message: colliding messages of every length from k to k + 2^k - 1 blocks
+#[derive(Clone, Debug)]
+pub struct ExpandableMessage {
+    pub stages: Vec<ExpandableStage>,
+    pub final_state: Vec<u8>,
+}
+
+impl ExpandableMessage {
+    pub fn k(&self) -> usize {
+        self.stages.len()
+    }
+
+    pub fn min_blocks(&self) -> usize {
+        self.k()
+    }
+
+    pub fn max_blocks(&self) -> usize {
+        self.k() + (1 << self.k()) - 1
+    }
+
+    /// The message of `blocks` blocks, all of them leading to `final_state`
+    pub fn produce(&self, blocks: usize) -> Option<Vec<u8>> {
+        if blocks < self.min_blocks() || blocks > self.max_blocks() {
+            return None;
+        }
+        let extra = blocks - self.k();
+        let k = self.k();
+        Some(self.stages.iter()
+            .enumerate()
+            .flat_map(|(i, stage)| {
+                let long = (extra >> (k - 1 - i)) & 1 == 1;
+                if long { stage.long.iter() } else { stage.short.iter() }
+            })
+            .cloned()
+            .collect())
+    }
+}
+
+/// Builds the stages with 2^(k-1), ..., 2, 1 dummy blocks in front of the long message, k birthday
+/// searches plus 2^k - 1 dummy block compressions
+pub fn expandable_message(hash: &MdHash, state: &[u8], k: usize) -> ExpandableMessage {
+    let dummy = [0u8; BLOCK_SIZE];
+    let mut state = state.to_vec();
+    let mut stages = Vec::with_capacity(k);
+
+    for j in (0..k).rev() {
+        let mut long: Vec<u8> = dummy.iter().cloned().cycle().take(BLOCK_SIZE << j).collect();
+        let long_state = hash.iterate(&state, &long);
+        let collision = find_cross_collision(hash, &state, &long_state);
+        let [short, last] = collision.blocks;
+        long.extend_from_slice(&last);
+        stages.push(ExpandableStage { short, long });
+        state = collision.next_state;
+    }
+
+    ExpandableMessage { stages, final_state: state }
+}
+
+#[derive(Debug)]
+pub struct SecondPreimage {
+    pub message: Vec<u8>,
+    pub expandable_compressions: u64,
+    pub intermediate_compressions: u64,
+    pub bridge_compressions: u64,
+}
+
+impl SecondPreimage {
+    pub fn compressions(&self) -> u64 {
+        self.expandable_compressions + self.intermediate_compressions + self.bridge_compressions
+    }
+}
+
+/// Brute force second preimage cost for a b-bit state
+pub fn naive_second_preimage_work(hash: &MdHash) -> u64 {
+    1 << (hash.state_bytes() * 8)
+}
+
+/// k * 2^(b/2 + 1) + 2^k for the expandable message and 2^(b - k) for the bridge block
+pub fn expected_second_preimage_work(hash: &MdHash, k: usize) -> u64 {
+    let b = hash.state_bytes() * 8;
+    k as u64 * (1 << (b / 2 + 1)) + (1 << k) + (1 << (b - k))
+}
+
+/// Kelsey-Schneier: a message of the same length and hash as `message`, which should be at least
+/// 2^k + k + 1 blocks long. Returns `None` when too short.
+pub fn second_preimage(hash: &MdHash, message: &[u8], k: usize) -> Option<SecondPreimage> {
+    let block_count = message.len() / BLOCK_SIZE;
+    let start = hash.compressions();
+    let expandable = expandable_message(hash, hash.initial_state(), k);
+    let expandable_compressions = hash.compressions() - start;
+
+    // state after i blocks of the original message, for the prefix lengths the expandable message can cover
+    let start = hash.compressions();
+    let mut intermediate: HashMap<Vec<u8>, usize> = HashMap::new();
+    let mut state = hash.initial_state().to_vec();
+    for (i, message_block) in message.chunks(BLOCK_SIZE).take(block_count).enumerate() {
+        state = hash.compress(&state, message_block);
+        let prefix_blocks = i + 1;
+        if prefix_blocks > expandable.min_blocks() && prefix_blocks <= expandable.max_blocks() + 1 {
+            intermediate.entry(state.clone()).or_insert(prefix_blocks);
+        }
+    }
+    let intermediate_compressions = hash.compressions() - start;
+    if intermediate.is_empty() {
+        return None;
+    }
+
+    let start = hash.compressions();
+    let (bridge, prefix_blocks) = loop {
+        let bridge = util::generate_random_bytes(BLOCK_SIZE);
+        if let Some(&prefix_blocks) = intermediate.get(&hash.compress(&expandable.final_state, &bridge)) {
+            break (bridge, prefix_blocks);
+        }
+    };
+    let bridge_compressions = hash.compressions() - start;
+
+    let mut forged = expandable.produce(prefix_blocks - 1)?;
+    forged.extend_from_slice(&bridge);
+    forged.extend_from_slice(&message[prefix_blocks * BLOCK_SIZE..]);
+
+    Some(SecondPreimage { message: forged, expandable_compressions, intermediate_compressions, bridge_compressions })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn md_pad_works() {
+        assert_eq!(BLOCK_SIZE, md_pad(b"").len());
+        assert_eq!(2 * BLOCK_SIZE, md_pad(b"YELLOW SUBMARINE").len());
+        let padded = md_pad(b"abc");
+        assert_eq!(0x80, padded[3]);
+        assert_eq!(24, padded[BLOCK_SIZE - 1]);
+    }
+
+    #[test]
+    fn joux_multicollision_works() {
+        let hash = MdHash::with_state_bits(16);
+        let multicollision = joux_multicollision(&hash, hash.initial_state(), 4);
+        assert_eq!(16, multicollision.message_count());
+        assert_eq!(hash.compressions(), multicollision.compressions());
+
+        let messages = multicollision.messages();
+        let digest = hash.hash(&messages[0]);
+        for (i, message) in messages.iter().enumerate() {
+            assert_eq!(digest, hash.hash(message));
+            assert!(messages[..i].iter().all(|other| other != message));
+        }
+    }
+
+    #[test]
+    fn concatenated_hash_collision_works() {
+        let cheap = MdHash::with_state_bits(16);
+        let strong = MdHash::with_state_bits(24);
+        let collision = concatenated_hash_collision(&cheap, &strong);
+        assert_eq!(cheap.compressions(), collision.cheap_compressions);
+        assert_eq!(strong.compressions(), collision.strong_compressions);
+        let [ref a, ref b] = collision.messages;
+        assert_ne!(a, b);
+        assert_eq!(cheap.hash(a), cheap.hash(b));
+        assert_eq!(strong.hash(a), strong.hash(b));
+        assert!(collision.stages >= 12);
+    }
+
+    #[test]
+    fn expandable_message_works() {
+        let hash = MdHash::with_state_bits(16);
+        let expandable = expandable_message(&hash, hash.initial_state(), 4);
+        assert_eq!((4, 19), (expandable.min_blocks(), expandable.max_blocks()));
+        for blocks in 4..20 {
+            let message = expandable.produce(blocks).unwrap();
+            assert_eq!(blocks * BLOCK_SIZE, message.len());
+            assert_eq!(expandable.final_state, hash.iterate(hash.initial_state(), &message));
+        }
+        assert_eq!(None, expandable.produce(3));
+        assert_eq!(None, expandable.produce(20));
+    }
+
+    #[test]
+    fn second_preimage_works() {
+        let hash = MdHash::with_state_bits(24);
+        let k = 10;
+        let message = util::generate_random_bytes(((1 << k) + k + 1) * BLOCK_SIZE + 5);
+        let result = second_preimage(&hash, &message, k).unwrap();
+
+        assert_eq!(hash.compressions(), result.compressions());
+        assert_ne!(message, result.message);
+        assert_eq!(message.len(), result.message.len());
+        assert_eq!(hash.hash(&message), hash.hash(&result.message));
+        assert!(result.compressions() < naive_second_preimage_work(&hash) / 16);
+        assert!(result.compressions() < expected_second_preimage_work(&hash, k) * 8);
+    }
+}
@@ -0,0 +1,337 @@
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::thread;
+
+use num_bigint::BigUint;
+use num_traits::{One, Zero};
+
+use dh;
+use dh::DhParams;
+
+pub enum Message {
+    KeyExchange { p: BigUint, g: BigUint, public: BigUint },
+    Negotiate { p: BigUint, g: BigUint },
+    Ack { p: BigUint, g: BigUint },
+    PublicKey(BigUint),
+    Encrypted(Vec<u8>),
+    Done,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Protocol {
+    /// A->B: p, g, A; B->A: B
+    ParamsWithKey,
+    /// A->B: p, g; B->A: ACK; A->B: A; B->A: B
+    NegotiatedGroup,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum MaliciousG {
+    One,
+    P,
+    PMinusOne,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum MitmStrategy {
+    Relay,
+    PublicKeyAsP,
+    /// Only possible with `Protocol::NegotiatedGroup`, since both sides have to agree on the tampered g
+    MaliciousGenerator(MaliciousG),
+}
+
+pub struct ExchangeReport {
+    pub bob_received: Vec<Option<Vec<u8>>>,
+    pub alice_echoes: Vec<Option<Vec<u8>>>,
+    pub mallory_recovered: Vec<Vec<u8>>,
+}
+
+pub fn run_exchange(
+    params: &DhParams,
+    protocol: Protocol,
+    mallory: Option<MitmStrategy>,
+    messages: &[Vec<u8>],
+) -> ExchangeReport {
+    if let Some(MitmStrategy::MaliciousGenerator(_)) = mallory {
+        assert_eq!(Protocol::NegotiatedGroup, protocol);
+    }
+
+    let (to_alice, alice_inbox) = channel();
+    let (to_bob, bob_inbox) = channel();
+
+    let (alice_outbox, bob_outbox, mallory_handle) = match mallory {
+        None => (to_bob, to_alice, None),
+        Some(strategy) => {
+            let (alice_outbox, from_alice) = channel();
+            let (bob_outbox, from_bob) = channel();
+            let handle = thread::spawn(move || {
+                mallory_actor(protocol, strategy, from_alice, to_bob, from_bob, to_alice)
+            });
+            (alice_outbox, bob_outbox, Some(handle))
+        }
+    };
+
+    let alice_params = params.clone();
+    let alice_messages = messages.to_vec();
+    let alice_handle = thread::spawn(move || {
+        alice_actor(alice_params, protocol, alice_messages, alice_inbox, alice_outbox)
+    });
+    let bob_handle = thread::spawn(move || bob_actor(protocol, bob_inbox, bob_outbox));
+
+    let alice_echoes = alice_handle.join().unwrap();
+    let bob_received = bob_handle.join().unwrap();
+    let mallory_recovered = match mallory_handle {
+        Some(handle) => handle.join().unwrap(),
+        None => Vec::new(),
+    };
+
+    ExchangeReport { bob_received, alice_echoes, mallory_recovered }
+}
+
+fn recv_public_key(inbox: &Receiver<Message>) -> BigUint {
+    match inbox.recv().unwrap() {
+        Message::PublicKey(public) => public,
+        _ => panic!("Expected public key"),
+    }
+}
+
+fn alice_actor(
+    params: DhParams,
+    protocol: Protocol,
+    messages: Vec<Vec<u8>>,
+    inbox: Receiver<Message>,
+    outbox: Sender<Message>,
+) -> Vec<Option<Vec<u8>>> {
+    let shared_secret = match protocol {
+        Protocol::ParamsWithKey => {
+            let keypair = params.generate_keypair();
+            outbox.send(Message::KeyExchange {
+                p: params.p.clone(),
+                g: params.g.clone(),
+                public: keypair.public.clone(),
+            }).unwrap();
+            let bob_public = recv_public_key(&inbox);
+            params.shared_secret(&keypair, &bob_public)
+        }
+        Protocol::NegotiatedGroup => {
+            outbox.send(Message::Negotiate { p: params.p.clone(), g: params.g.clone() }).unwrap();
+            let group = match inbox.recv().unwrap() {
+                Message::Ack { p, g } => DhParams::new(p, g),
+                _ => panic!("Expected ACK"),
+            };
+            let keypair = group.generate_keypair();
+            outbox.send(Message::PublicKey(keypair.public.clone())).unwrap();
+            let bob_public = recv_public_key(&inbox);
+            group.shared_secret(&keypair, &bob_public)
+        }
+    };
+
+    let mut echoes = Vec::with_capacity(messages.len());
+    for msg in messages {
+        outbox.send(Message::Encrypted(dh::encrypt_message(&shared_secret, &msg))).unwrap();
+        match inbox.recv().unwrap() {
+            Message::Encrypted(data) => echoes.push(dh::decrypt_message(&shared_secret, &data)),
+            _ => panic!("Expected encrypted echo"),
+        }
+    }
+    outbox.send(Message::Done).unwrap();
+
+    echoes
+}
+
+fn bob_actor(
+    protocol: Protocol,
+    inbox: Receiver<Message>,
+    outbox: Sender<Message>,
+) -> Vec<Option<Vec<u8>>> {
+    let shared_secret = match protocol {
+        Protocol::ParamsWithKey => {
+            let (group, alice_public) = match inbox.recv().unwrap() {
+                Message::KeyExchange { p, g, public } => (DhParams::new(p, g), public),
+                _ => panic!("Expected key exchange"),
+            };
+            let keypair = group.generate_keypair();
+            outbox.send(Message::PublicKey(keypair.public.clone())).unwrap();
+            group.shared_secret(&keypair, &alice_public)
+        }
+        Protocol::NegotiatedGroup => {
+            let group = match inbox.recv().unwrap() {
+                Message::Negotiate { p, g } => DhParams::new(p, g),
+                _ => panic!("Expected group negotiation"),
+            };
+            outbox.send(Message::Ack { p: group.p.clone(), g: group.g.clone() }).unwrap();
+            let alice_public = recv_public_key(&inbox);
+            let keypair = group.generate_keypair();
+            outbox.send(Message::PublicKey(keypair.public.clone())).unwrap();
+            group.shared_secret(&keypair, &alice_public)
+        }
+    };
+
+    let mut received = Vec::new();
+    loop {
+        match inbox.recv().unwrap() {
+            Message::Encrypted(data) => {
+                let decrypted = dh::decrypt_message(&shared_secret, &data);
+                let echo = dh::encrypt_message(&shared_secret, decrypted.as_ref().map_or(&[][..], |d| &d[..]));
+                received.push(decrypted);
+                outbox.send(Message::Encrypted(echo)).unwrap();
+            }
+            Message::Done => break,
+            _ => panic!("Expected encrypted message"),
+        }
+    }
+
+    received
+}
+
+fn malicious_g(p: &BigUint, g: MaliciousG) -> BigUint {
+    match g {
+        MaliciousG::One => BigUint::one(),
+        MaliciousG::P => p.clone(),
+        MaliciousG::PMinusOne => p - BigUint::one(),
+    }
+}
+
+/// Shared secrets Alice and Bob can end up with after the tampering, given the group modulus
+fn forced_secrets(strategy: MitmStrategy, p: &BigUint) -> Vec<BigUint> {
+    match strategy {
+        MitmStrategy::Relay => Vec::new(),
+        MitmStrategy::PublicKeyAsP => vec![BigUint::zero()],
+        MitmStrategy::MaliciousGenerator(MaliciousG::One) => vec![BigUint::one()],
+        MitmStrategy::MaliciousGenerator(MaliciousG::P) => vec![BigUint::zero()],
+        MitmStrategy::MaliciousGenerator(MaliciousG::PMinusOne) => vec![BigUint::one(), p - BigUint::one()],
+    }
+}
+
+fn mallory_actor(
+    protocol: Protocol,
+    strategy: MitmStrategy,
+    from_alice: Receiver<Message>,
+    to_bob: Sender<Message>,
+    from_bob: Receiver<Message>,
+    to_alice: Sender<Message>,
+) -> Vec<Vec<u8>> {
+    let replace_public = |public: BigUint, p: &BigUint| {
+        if strategy == MitmStrategy::PublicKeyAsP {
+            p.clone()
+        } else {
+            public
+        }
+    };
+
+    let p = match protocol {
+        Protocol::ParamsWithKey => {
+            let p = match from_alice.recv().unwrap() {
+                Message::KeyExchange { p, g, public } => {
+                    let public = replace_public(public, &p);
+                    to_bob.send(Message::KeyExchange { p: p.clone(), g, public }).unwrap();
+                    p
+                }
+                _ => panic!("Expected key exchange"),
+            };
+            let bob_public = recv_public_key(&from_bob);
+            to_alice.send(Message::PublicKey(replace_public(bob_public, &p))).unwrap();
+            p
+        }
+        Protocol::NegotiatedGroup => {
+            let (p, g) = match from_alice.recv().unwrap() {
+                Message::Negotiate { p, g } => match strategy {
+                    MitmStrategy::MaliciousGenerator(bad_g) => {
+                        let g = malicious_g(&p, bad_g);
+                        (p, g)
+                    }
+                    _ => (p, g),
+                },
+                _ => panic!("Expected group negotiation"),
+            };
+            to_bob.send(Message::Negotiate { p: p.clone(), g: g.clone() }).unwrap();
+            match from_bob.recv().unwrap() {
+                Message::Ack { .. } => to_alice.send(Message::Ack { p: p.clone(), g }).unwrap(),
+                _ => panic!("Expected ACK"),
+            }
+            let alice_public = recv_public_key(&from_alice);
+            to_bob.send(Message::PublicKey(replace_public(alice_public, &p))).unwrap();
+            let bob_public = recv_public_key(&from_bob);
+            to_alice.send(Message::PublicKey(replace_public(bob_public, &p))).unwrap();
+            p
+        }
+    };
+
+    let candidates = forced_secrets(strategy, &p);
+    let mut recovered = Vec::new();
+    loop {
+        match from_alice.recv().unwrap() {
+            Message::Encrypted(data) => {
+                to_bob.send(Message::Encrypted(data.clone())).unwrap();
+                let echo = from_bob.recv().unwrap();
+                // a wrong secret passes the padding check now and then, Bob's echo of the same plaintext confirms the right one
+                if let Message::Encrypted(ref echo_data) = echo {
+                    let confirmed = candidates.iter()
+                        .filter_map(|s| dh::decrypt_message(s, &data).filter(|plain_text| dh::decrypt_message(s, echo_data).as_ref() == Some(plain_text)))
+                        .next();
+                    if let Some(plain_text) = confirmed {
+                        recovered.push(plain_text);
+                    }
+                }
+                to_alice.send(echo).unwrap();
+            }
+            Message::Done => {
+                to_bob.send(Message::Done).unwrap();
+                break;
+            }
+            _ => panic!("Expected encrypted message"),
+        }
+    }
+
+    recovered
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_messages() -> Vec<Vec<u8>> {
+        vec![
+            b"Meet me at the usual place at noon".to_vec(),
+            b"Bring the documents".to_vec(),
+            b"hi".to_vec(),
+            b"YELLOW SUBMARINE".to_vec(),
+        ]
+    }
+
+    fn assert_exchange_intact(report: &ExchangeReport, messages: &[Vec<u8>]) {
+        let expected: Vec<Option<Vec<u8>>> = messages.iter().cloned().map(Some).collect();
+        assert_eq!(expected, report.bob_received);
+        assert_eq!(expected, report.alice_echoes);
+    }
+
+    #[test]
+    fn exchange_without_mallory_works() {
+        let messages = test_messages();
+        for &protocol in &[Protocol::ParamsWithKey, Protocol::NegotiatedGroup] {
+            let report = run_exchange(&DhParams::nist(), protocol, None, &messages);
+            assert_exchange_intact(&report, &messages);
+            assert!(report.mallory_recovered.is_empty());
+        }
+    }
+
+    #[test]
+    fn public_key_as_p_recovers_messages() {
+        let messages = test_messages();
+        for &protocol in &[Protocol::ParamsWithKey, Protocol::NegotiatedGroup] {
+            let report = run_exchange(&DhParams::nist(), protocol, Some(MitmStrategy::PublicKeyAsP), &messages);
+            assert_exchange_intact(&report, &messages);
+            assert_eq!(messages, report.mallory_recovered);
+        }
+    }
+
+    #[test]
+    fn malicious_g_recovers_messages() {
+        let messages = test_messages();
+        for &bad_g in &[MaliciousG::One, MaliciousG::P, MaliciousG::PMinusOne] {
+            let strategy = MitmStrategy::MaliciousGenerator(bad_g);
+            let report = run_exchange(&DhParams::nist(), Protocol::NegotiatedGroup, Some(strategy), &messages);
+            assert_exchange_intact(&report, &messages);
+            assert_eq!(messages, report.mallory_recovered);
+        }
+    }
+}
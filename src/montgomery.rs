@@ -0,0 +1,206 @@
+use std::mem;
+
+use num_bigint::BigUint;
+use num_traits::{One, Zero};
+
+use ec::{Curve, Point};
+use numtheory;
+use util;
+
+const CRYPTOPALS_P: &str = "233970423115425145524320034830162017933";
+const CRYPTOPALS_A: u32 = 534;
+const CRYPTOPALS_B: u32 = 1;
+const CRYPTOPALS_GU: u32 = 4;
+const CRYPTOPALS_GV: &str = "85518893674295321206118380980485522083";
+const CRYPTOPALS_N: &str = "29246302889428143187362802287225875743";
+const CRYPTOPALS_CURVE_ORDER: &str = "233970423115425145498902418297807005944";
+
+/// Bv^2 = u^3 + Au^2 + u over GF(p), with a base point `g` (as (u, v)) of prime order `n`
+#[derive(Clone, PartialEq, Debug)]
+pub struct MontgomeryCurve {
+    pub p: BigUint,
+    pub a: BigUint,
+    pub b: BigUint,
+    pub g: Point,
+    pub n: BigUint,
+    /// Number of points on the whole curve, `n` times the cofactor
+    pub order: BigUint,
+}
+
+impl MontgomeryCurve {
+    pub fn new(p: BigUint, a: BigUint, b: BigUint, g: Point, n: BigUint, order: BigUint) -> MontgomeryCurve {
+        MontgomeryCurve { a: a % &p, b: b % &p, p, g, n, order }
+    }
+
+    /// v^2 = u^3 + 534u^2 + u, the Montgomery form of `Curve::cryptopals`
+    pub fn cryptopals() -> MontgomeryCurve {
+        let dec = |s: &str| BigUint::parse_bytes(s.as_bytes(), 10).unwrap();
+        MontgomeryCurve::new(dec(CRYPTOPALS_P), BigUint::from(CRYPTOPALS_A), BigUint::from(CRYPTOPALS_B),
+                             Point::new(BigUint::from(CRYPTOPALS_GU), dec(CRYPTOPALS_GV)),
+                             dec(CRYPTOPALS_N), dec(CRYPTOPALS_CURVE_ORDER))
+    }
+
+    pub fn field_bytes(&self) -> usize {
+        (self.p.bits() as usize).div_ceil(8)
+    }
+
+    /// u coordinate of the base point
+    pub fn base_u(&self) -> BigUint {
+        match self.g {
+            Point::Infinity => BigUint::zero(),
+            Point::Affine { ref x, .. } => x.clone(),
+        }
+    }
+
+    /// Number of points on the quadratic twist, 2p + 2 minus the curve order
+    pub fn twist_order(&self) -> BigUint {
+        &self.p * 2u32 + 2u32 - &self.order
+    }
+
+    fn sub(&self, a: &BigUint, b: &BigUint) -> BigUint {
+        (a + &self.p - (b % &self.p)) % &self.p
+    }
+
+    fn div(&self, a: &BigUint, b: &BigUint) -> BigUint {
+        a * numtheory::invmod(b, &self.p).unwrap() % &self.p
+    }
+
+    /// (u^3 + Au^2 + u) / B, a square for points on the curve and a non-square for points on the twist
+    fn v_squared(&self, u: &BigUint) -> BigUint {
+        let rhs = (u * u * u + &self.a * u * u + u) % &self.p;
+        self.div(&rhs, &self.b)
+    }
+
+    /// Whether some v makes (u, v) a point on the curve, otherwise u belongs to the twist
+    pub fn is_on_curve_u(&self, u: &BigUint) -> bool {
+        let u = u % &self.p;
+        numtheory::sqrt_mod(&self.v_squared(&u), &self.p).is_some()
+    }
+
+    /// A u coordinate of a random point on the quadratic twist
+    pub fn random_twist_u(&self) -> BigUint {
+        loop {
+            let u = util::generate_random_biguint_below(&self.p);
+            if !self.is_on_curve_u(&u) {
+                return u;
+            }
+        }
+    }
+
+    /// u coordinate of k times the point with coordinate u, without ever looking at v. The same formulas
+    /// work for u on the twist. The point at infinity comes out as 0.
+    pub fn ladder(&self, u: &BigUint, k: &BigUint) -> BigUint {
+        let p = &self.p;
+        let u = u % p;
+        let (mut u2, mut w2) = (BigUint::one(), BigUint::zero());
+        let (mut u3, mut w3) = (u.clone(), BigUint::one());
+        for i in (0..p.bits().max(k.bits())).rev() {
+            let bit = k.bit(i);
+            if bit {
+                mem::swap(&mut u2, &mut u3);
+                mem::swap(&mut w2, &mut w3);
+            }
+            let sum = self.sub(&(&u2 * &u3), &(&w2 * &w3));
+            let diff = self.sub(&(&u2 * &w3), &(&w2 * &u3));
+            u3 = &sum * &sum % p;
+            w3 = &u * &diff * &diff % p;
+            let squares = self.sub(&(&u2 * &u2), &(&w2 * &w2));
+            let new_u2 = &squares * &squares % p;
+            w2 = BigUint::from(4u32) * &u2 * &w2 % p * ((&u2 * &u2 + &self.a * &u2 * &w2 + &w2 * &w2) % p) % p;
+            u2 = new_u2;
+            if bit {
+                mem::swap(&mut u2, &mut u3);
+                mem::swap(&mut w2, &mut w3);
+            }
+        }
+        // w2^(p - 2) is the inverse of w2, or 0 for the point at infinity
+        u2 * w2.modpow(&(p - 2u32), p) % p
+    }
+
+    /// u(P + Q) from u(P), u(Q) and u(P - Q), none of them the point at infinity:
+    /// u(P + Q) u(P - Q) (u(P) - u(Q))^2 = (u(P) u(Q) - 1)^2
+    pub fn differential_add(&self, u_p: &BigUint, u_q: &BigUint, u_diff: &BigUint) -> BigUint {
+        let numerator = self.sub(&(u_p * u_q), &BigUint::one());
+        let difference = self.sub(u_p, u_q);
+        self.div(&(&numerator * &numerator), &(u_diff * &difference * &difference))
+    }
+
+    /// The equivalent short Weierstrass curve, y^2 = x^3 + ax + b with a = (3 - A^2) / 3B^2 and
+    /// b = (2A^3 - 9A) / 27B^3
+    pub fn to_weierstrass(&self) -> Curve {
+        let (a, b) = (&self.a, &self.b);
+        let weierstrass_a = self.div(&self.sub(&BigUint::from(3u32), &(a * a)), &(b * b * 3u32));
+        let weierstrass_b = self.div(&self.sub(&(a * a * a * 2u32), &(a * 9u32)), &(b * b * b * 27u32));
+        Curve::new(self.p.clone(), weierstrass_a, weierstrass_b, self.to_weierstrass_point(&self.g),
+                   self.n.clone(), self.order.clone())
+    }
+
+    /// x = u / B + A / 3B
+    pub fn weierstrass_x(&self, u: &BigUint) -> BigUint {
+        (self.div(u, &self.b) + self.div(&self.a, &(&self.b * 3u32))) % &self.p
+    }
+
+    /// (u, v) to (u / B + A / 3B, v / B)
+    pub fn to_weierstrass_point(&self, point: &Point) -> Point {
+        match *point {
+            Point::Infinity => Point::Infinity,
+            Point::Affine { ref x, ref y } => Point::new(self.weierstrass_x(x), self.div(y, &self.b)),
+        }
+    }
+
+    /// (x, y) to (Bx - A / 3, By)
+    pub fn from_weierstrass_point(&self, point: &Point) -> Point {
+        match *point {
+            Point::Infinity => Point::Infinity,
+            Point::Affine { ref x, ref y } => {
+                let u = self.sub(&(&self.b * x), &self.div(&self.a, &BigUint::from(3u32)));
+                Point::new(u, &self.b * y % &self.p)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn u_of(point: &Point) -> BigUint {
+        match *point {
+            Point::Infinity => BigUint::zero(),
+            Point::Affine { ref x, .. } => x.clone(),
+        }
+    }
+
+    #[test]
+    fn converts_to_cryptopals_curve() {
+        let curve = MontgomeryCurve::cryptopals();
+        assert_eq!(Curve::cryptopals(), curve.to_weierstrass());
+        assert!(curve.is_on_curve_u(&u_of(&curve.g)));
+
+        let weierstrass = Curve::cryptopals();
+        let point = weierstrass.random_point();
+        assert_eq!(point, curve.to_weierstrass_point(&curve.from_weierstrass_point(&point)));
+    }
+
+    #[test]
+    fn ladder_matches_weierstrass() {
+        let curve = MontgomeryCurve::cryptopals();
+        let weierstrass = curve.to_weierstrass();
+        let k = util::generate_random_biguint_below(&curve.n);
+        let expected = curve.from_weierstrass_point(&weierstrass.scalar_mul(&weierstrass.g, &k));
+        assert_eq!(u_of(&expected), curve.ladder(&curve.base_u(), &k));
+        assert!(curve.ladder(&curve.base_u(), &curve.n).is_zero());
+
+        let u2 = curve.ladder(&curve.base_u(), &BigUint::from(2u32));
+        let u3 = curve.differential_add(&u2, &curve.base_u(), &curve.base_u());
+        assert_eq!(curve.ladder(&curve.base_u(), &BigUint::from(3u32)), u3);
+    }
+
+    #[test]
+    fn twist_points_have_twist_order() {
+        let curve = MontgomeryCurve::cryptopals();
+        let u = curve.random_twist_u();
+        assert!(!curve.is_on_curve_u(&u));
+        assert!(curve.ladder(&u, &curve.twist_order()).is_zero());
+    }
+}
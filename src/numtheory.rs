@@ -0,0 +1,268 @@
+use num_bigint::{BigInt, BigUint, Sign};
+use num_integer::Integer;
+use num_traits::{One, ToPrimitive, Zero};
+
+use util;
+
+const SMALL_PRIMES: [u32; 25] = [
+    2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37, 41, 43, 47,
+    53, 59, 61, 67, 71, 73, 79, 83, 89, 97
+];
+const MILLER_RABIN_ROUNDS: usize = 32;
+
+/// Returns (g, x, y) such that a * x + b * y = g = gcd(a, b)
+pub fn egcd(a: &BigInt, b: &BigInt) -> (BigInt, BigInt, BigInt) {
+    let (mut old_r, mut r) = (a.clone(), b.clone());
+    let (mut old_x, mut x) = (BigInt::one(), BigInt::zero());
+    let (mut old_y, mut y) = (BigInt::zero(), BigInt::one());
+
+    while !r.is_zero() {
+        let q = &old_r / &r;
+        let next_r = &old_r - &q * &r;
+        old_r = r;
+        r = next_r;
+        let next_x = &old_x - &q * &x;
+        old_x = x;
+        x = next_x;
+        let next_y = &old_y - &q * &y;
+        old_y = y;
+        y = next_y;
+    }
+
+    (old_r, old_x, old_y)
+}
+
+pub fn invmod(a: &BigUint, m: &BigUint) -> Option<BigUint> {
+    let m_signed = BigInt::from_biguint(Sign::Plus, m.clone());
+    let (g, x, _) = egcd(&BigInt::from_biguint(Sign::Plus, a % m), &m_signed);
+    if !g.is_one() {
+        return None;
+    }
+    x.mod_floor(&m_signed).to_biguint()
+}
+
+/// Solves x = residues[i] mod moduli[i] for pairwise coprime moduli, returns (x, product of moduli)
+pub fn crt(residues: &[BigUint], moduli: &[BigUint]) -> Option<(BigUint, BigUint)> {
+    assert_eq!(residues.len(), moduli.len());
+    let product: BigUint = moduli.iter().product();
+
+    let mut x = BigUint::zero();
+    for (r, m) in residues.iter().zip(moduli.iter()) {
+        let m_s = &product / m;
+        let inv = invmod(&m_s, m)?;
+        x += r * &m_s * inv;
+    }
+
+    Some((x % &product, product))
+}
+
+/// Integer k-th root, if `n` is a perfect k-th power
+pub fn exact_root(n: &BigUint, k: u32) -> Option<BigUint> {
+    let root = n.nth_root(k);
+    if root.pow(k) == *n {
+        Some(root)
+    } else {
+        None
+    }
+}
+
+/// Tonelli-Shanks square root mod an odd prime p, `None` for non-residues
+pub fn sqrt_mod(a: &BigUint, p: &BigUint) -> Option<BigUint> {
+    let a = a % p;
+    if a.is_zero() {
+        return Some(a);
+    }
+    let one = BigUint::one();
+    let p_minus_one = p - &one;
+    let half = &p_minus_one >> 1;
+    if a.modpow(&half, p) != one {
+        return None;
+    }
+
+    let mut q = p_minus_one.clone();
+    let mut s = 0u32;
+    while q.is_even() {
+        q >>= 1;
+        s += 1;
+    }
+    let mut z = BigUint::from(2u32);
+    while z.modpow(&half, p) != p_minus_one {
+        z += 1u32;
+    }
+
+    let mut m = s;
+    let mut c = z.modpow(&q, p);
+    let mut t = a.modpow(&q, p);
+    let mut r = a.modpow(&((&q + 1u32) >> 1), p);
+    while t != one {
+        let mut i = 0;
+        let mut t2i = t.clone();
+        while t2i != one {
+            t2i = &t2i * &t2i % p;
+            i += 1;
+        }
+        let b = c.modpow(&(BigUint::one() << (m - i - 1)), p);
+        m = i;
+        c = &b * &b % p;
+        t = t * &c % p;
+        r = r * b % p;
+    }
+    Some(r)
+}
+
+pub fn is_probable_prime(n: &BigUint) -> bool {
+    for &p in SMALL_PRIMES.iter() {
+        let p = BigUint::from(p);
+        if *n == p {
+            return true;
+        }
+        if (n % &p).is_zero() {
+            return false;
+        }
+    }
+    if *n < BigUint::from(2u32) {
+        return false;
+    }
+
+    let one = BigUint::one();
+    let n_minus_one = n - &one;
+    let mut d = n_minus_one.clone();
+    let mut s = 0;
+    while d.is_even() {
+        d >>= 1;
+        s += 1;
+    }
+
+    let two = BigUint::from(2u32);
+    let base_bound = n - 3u32;
+    'witness: for _ in 0..MILLER_RABIN_ROUNDS {
+        let a = util::generate_random_biguint_below(&base_bound) + &two;
+        let mut x = a.modpow(&d, n);
+        if x == one || x == n_minus_one {
+            continue;
+        }
+        for _ in 1..s {
+            x = x.modpow(&two, n);
+            if x == n_minus_one {
+                continue 'witness;
+            }
+        }
+        return false;
+    }
+
+    true
+}
+
+/// Random integer of exactly `bits` bits, the two top bits set so products of two such numbers have 2 * `bits` bits
+pub fn random_odd_with_bits(bits: usize) -> BigUint {
+    assert!(bits >= 2);
+    let bytes = util::generate_random_bytes(bits.div_ceil(8));
+    let mut n = BigUint::from_bytes_be(&bytes) >> (bytes.len() * 8 - bits);
+    n.set_bit(bits as u64 - 1, true);
+    n.set_bit(bits as u64 - 2, true);
+    n.set_bit(0, true);
+    n
+}
+
+pub fn generate_prime(bits: usize) -> BigUint {
+    loop {
+        let candidate = random_odd_with_bits(bits);
+        if is_probable_prime(&candidate) {
+            return candidate;
+        }
+    }
+}
+
+/// Prime p of at least `bits` bits with p - 1 = 2 * (distinct primes below `factor_bound`), so discrete logs
+/// mod p are cheap with Pohlig-Hellman. Primes in `avoid` aren't used. Returns p and the prime factors of p - 1,
+/// 2 included.
+pub fn generate_smooth_prime(bits: usize, factor_bound: u64, avoid: &[u64]) -> (BigUint, Vec<u64>) {
+    let bound = BigUint::from(factor_bound);
+    loop {
+        let mut factors = vec![2u64];
+        let mut product = BigUint::from(2u32);
+        while (product.bits() as usize) < bits {
+            let r = util::generate_random_biguint_below(&bound);
+            let r_u64 = r.to_u64().unwrap();
+            if r_u64 > 2 && !factors.contains(&r_u64) && !avoid.contains(&r_u64) && is_probable_prime(&r) {
+                factors.push(r_u64);
+                product *= r;
+            }
+        }
+        let p = product + 1u32;
+        if is_probable_prime(&p) {
+            return (p, factors);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn invmod_works() {
+        assert_eq!(Some(BigUint::from(2753u32)), invmod(&BigUint::from(17u32), &BigUint::from(3120u32)));
+        assert_eq!(None, invmod(&BigUint::from(6u32), &BigUint::from(9u32)));
+    }
+
+    #[test]
+    fn crt_works() {
+        let residues = vec![BigUint::from(2u32), BigUint::from(3u32), BigUint::from(2u32)];
+        let moduli = vec![BigUint::from(3u32), BigUint::from(5u32), BigUint::from(7u32)];
+        assert_eq!(Some((BigUint::from(23u32), BigUint::from(105u32))), crt(&residues, &moduli));
+
+        let moduli = vec![BigUint::from(4u32), BigUint::from(6u32), BigUint::from(7u32)];
+        assert_eq!(None, crt(&residues, &moduli));
+    }
+
+    #[test]
+    fn exact_root_works() {
+        let n = BigUint::parse_bytes(b"123456789012345678901234567890", 10).unwrap();
+        assert_eq!(Some(n.clone()), exact_root(&n.pow(3), 3));
+        assert_eq!(None, exact_root(&(n.pow(3) + 1u32), 3));
+        assert_eq!(Some(n.clone()), exact_root(&n.pow(5), 5));
+    }
+
+    #[test]
+    fn sqrt_mod_works() {
+        // p = 1 mod 8 exercises the full Tonelli-Shanks loop
+        let p = BigUint::from(7681u32);
+        for a in 1..200u32 {
+            let a = BigUint::from(a);
+            match sqrt_mod(&a, &p) {
+                Some(r) => assert_eq!(a, &r * &r % &p),
+                None => assert!(a.modpow(&BigUint::from(3840u32), &p) != BigUint::one()),
+            }
+        }
+        assert_eq!(None, sqrt_mod(&BigUint::from(3u32), &BigUint::from(7u32)));
+    }
+
+    #[test]
+    fn is_probable_prime_works() {
+        let primes: Vec<u32> = vec![2, 3, 97, 101, 7919, 104729, 2147483647];
+        for p in primes {
+            assert!(is_probable_prime(&BigUint::from(p)));
+        }
+        let composites: Vec<u32> = vec![0, 1, 4, 561, 1105, 7917, 104731];
+        for c in composites {
+            assert!(!is_probable_prime(&BigUint::from(c)));
+        }
+    }
+
+    #[test]
+    fn generate_prime_works() {
+        let p = generate_prime(128);
+        assert_eq!(128, p.bits());
+        assert!(is_probable_prime(&p));
+    }
+
+    #[test]
+    fn generate_smooth_prime_works() {
+        let (p, factors) = generate_smooth_prime(128, 1 << 16, &[3, 5]);
+        assert!(p.bits() >= 128);
+        assert!(is_probable_prime(&p));
+        assert_eq!(p - 1u32, factors.iter().map(|&r| BigUint::from(r)).product());
+        assert!(!factors.contains(&3) && !factors.contains(&5));
+    }
+}
@@ -0,0 +1,380 @@
+use std::cell::Cell;
+
+use num_bigint::BigUint;
+use num_traits::One;
+use openssl::sha;
+
+use rsa;
+use util;
+use rsa::{RsaPrivateKey, RsaPublicKey};
+
+const SHA1_DIGEST_INFO: [u8; 15] = [
+    0x30, 0x21, 0x30, 0x09, 0x06, 0x05, 0x2b, 0x0e, 0x03, 0x02, 0x1a, 0x05, 0x00, 0x04, 0x14
+];
+const SHA256_DIGEST_INFO: [u8; 19] = [
+    0x30, 0x31, 0x30, 0x0d, 0x06, 0x09, 0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x02, 0x01,
+    0x05, 0x00, 0x04, 0x20
+];
+/// 00 01 FF ... FF 00 needs at least 8 bytes of FF in a well-formed block
+const MIN_SIGNATURE_PADDING: usize = 8;
+/// Same for the nonzero random padding string in 00 02 PS 00 M
+const MIN_ENCRYPTION_PADDING: usize = 8;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum HashAlgorithm {
+    Sha1,
+    Sha256,
+}
+
+impl HashAlgorithm {
+    pub fn digest(&self, msg: &[u8]) -> Vec<u8> {
+        match *self {
+            HashAlgorithm::Sha1 => sha::sha1(msg).to_vec(),
+            HashAlgorithm::Sha256 => sha::sha256(msg).to_vec(),
+        }
+    }
+
+    /// ASN.1 DER encoded DigestInfo header preceding the hash
+    pub fn digest_info_prefix(&self) -> &'static [u8] {
+        match *self {
+            HashAlgorithm::Sha1 => &SHA1_DIGEST_INFO,
+            HashAlgorithm::Sha256 => &SHA256_DIGEST_INFO,
+        }
+    }
+
+    fn digest_info(&self, msg: &[u8]) -> Vec<u8> {
+        let mut info = self.digest_info_prefix().to_vec();
+        info.extend_from_slice(&self.digest(msg));
+        info
+    }
+}
+
+fn modulus_len(n: &BigUint) -> usize {
+    (n.bits() as usize).div_ceil(8)
+}
+
+/// EMSA-PKCS1-v1_5 encoding: 00 01 FF ... FF 00 || DigestInfo || H(msg), `len` bytes long
+pub fn pad_signature_block(alg: HashAlgorithm, msg: &[u8], len: usize) -> Vec<u8> {
+    let digest_info = alg.digest_info(msg);
+    assert!(len >= digest_info.len() + 3 + MIN_SIGNATURE_PADDING);
+
+    let mut block = vec![0x00, 0x01];
+    block.resize(len - digest_info.len() - 1, 0xff);
+    block.push(0x00);
+    block.extend_from_slice(&digest_info);
+    block
+}
+
+pub fn sign(private: &RsaPrivateKey, alg: HashAlgorithm, msg: &[u8]) -> BigUint {
+    let block = pad_signature_block(alg, msg, modulus_len(&private.n));
+    private.sign(&rsa::bytes_to_int(&block))
+}
+
+fn signature_block(public: &RsaPublicKey, signature: &BigUint) -> Option<Vec<u8>> {
+    if *signature >= public.n {
+        return None;
+    }
+    let m = signature.modpow(&public.e, &public.n);
+    Some(rsa::int_to_bytes_padded(&m, modulus_len(&public.n)))
+}
+
+/// Verifier that parses the block left to right and never checks the hash is right-justified,
+/// so anything can follow the digest
+pub fn verify_sloppy(public: &RsaPublicKey, alg: HashAlgorithm, msg: &[u8], signature: &BigUint) -> bool {
+    let block = match signature_block(public, signature) {
+        Some(block) => block,
+        None => return false,
+    };
+    if block.len() < 3 || block[0] != 0x00 || block[1] != 0x01 {
+        return false;
+    }
+
+    let padding_end = match block[2..].iter().position(|&b| b != 0xff) {
+        Some(pos) => pos + 2,
+        None => return false,
+    };
+    if padding_end == 2 || block[padding_end] != 0x00 {
+        return false;
+    }
+
+    let digest_info = alg.digest_info(msg);
+    block[(padding_end + 1)..].starts_with(&digest_info)
+}
+
+/// Verifier that re-encodes the expected block and compares it in full
+pub fn verify_strict(public: &RsaPublicKey, alg: HashAlgorithm, msg: &[u8], signature: &BigUint) -> bool {
+    match signature_block(public, signature) {
+        Some(block) => block == pad_signature_block(alg, msg, block.len()),
+        None => false,
+    }
+}
+
+/// Bleichenbacher's e = 3 forgery: builds 00 01 FF 00 || DigestInfo || H(msg) || garbage and
+/// takes a cube root that lands inside the garbage range. Works against `verify_sloppy` only.
+pub fn forge_signature(public: &RsaPublicKey, alg: HashAlgorithm, msg: &[u8]) -> Option<BigUint> {
+    if public.e != BigUint::from(3u32) {
+        return None;
+    }
+    let len = modulus_len(&public.n);
+
+    let mut prefix = vec![0x00, 0x01, 0xff, 0x00];
+    prefix.extend_from_slice(&alg.digest_info(msg));
+    if prefix.len() >= len {
+        return None;
+    }
+
+    let mut low = prefix.clone();
+    low.resize(len, 0x00);
+    let mut high = prefix;
+    high.resize(len, 0xff);
+    let low = rsa::bytes_to_int(&low);
+    let high = rsa::bytes_to_int(&high);
+
+    let mut forged = low.nth_root(3);
+    if forged.pow(3) < low {
+        forged += 1u32;
+    }
+    if forged.pow(3) > high {
+        return None;
+    }
+
+    Some(forged)
+}
+
+/// EME-PKCS1-v1_5 encoding: 00 02 || nonzero random padding || 00 || msg, `len` bytes long
+pub fn pad_encryption_block(msg: &[u8], len: usize) -> Option<Vec<u8>> {
+    if msg.len() + 3 + MIN_ENCRYPTION_PADDING > len {
+        return None;
+    }
+
+    let mut block = vec![0x00, 0x02];
+    while block.len() < len - msg.len() - 1 {
+        block.extend(util::generate_random_bytes(len - msg.len() - 1 - block.len()).into_iter().filter(|&b| b != 0));
+    }
+    block.push(0x00);
+    block.extend_from_slice(msg);
+    Some(block)
+}
+
+pub fn unpad_encryption_block(block: &[u8]) -> Option<Vec<u8>> {
+    if block.len() < 3 || block[0] != 0x00 || block[1] != 0x02 {
+        return None;
+    }
+    let separator = block[2..].iter().position(|&b| b == 0x00)? + 2;
+    if separator < 2 + MIN_ENCRYPTION_PADDING {
+        return None;
+    }
+    Some(block[(separator + 1)..].to_vec())
+}
+
+pub fn encrypt(public: &RsaPublicKey, msg: &[u8]) -> Option<BigUint> {
+    let block = pad_encryption_block(msg, modulus_len(&public.n))?;
+    Some(public.encrypt(&rsa::bytes_to_int(&block)))
+}
+
+pub fn decrypt(private: &RsaPrivateKey, c: &BigUint) -> Option<Vec<u8>> {
+    let block = rsa::int_to_bytes_padded(&private.decrypt_crt(c), modulus_len(&private.n));
+    unpad_encryption_block(&block)
+}
+
+/// Decrypts ciphertexts and only reveals whether the plaintext block starts with 00 02
+pub struct PaddingOracle {
+    keypair: rsa::RsaKeyPair,
+    queries: Cell<u64>,
+}
+
+impl PaddingOracle {
+    pub fn new(bits: usize) -> PaddingOracle {
+        PaddingOracle { keypair: rsa::generate_keypair(bits, rsa::DEFAULT_E), queries: Cell::new(0) }
+    }
+
+    pub fn public_key(&self) -> &RsaPublicKey {
+        &self.keypair.public
+    }
+
+    pub fn queries(&self) -> u64 {
+        self.queries.get()
+    }
+
+    pub fn is_conforming(&self, c: &BigUint) -> bool {
+        self.queries.set(self.queries.get() + 1);
+        let m = self.keypair.private.decrypt_crt(c);
+        let block = rsa::int_to_bytes_padded(&m, modulus_len(&self.keypair.public.n));
+        block[0] == 0x00 && block[1] == 0x02
+    }
+}
+
+pub struct BleichenbacherProgress<'a> {
+    pub iteration: usize,
+    pub queries: u64,
+    pub intervals: &'a [(BigUint, BigUint)],
+}
+
+fn ceil_div(a: &BigUint, b: &BigUint) -> BigUint {
+    (a + b - 1u32) / b
+}
+
+fn conforming_multiplier(oracle: &PaddingOracle, c: &BigUint, s: &BigUint) -> bool {
+    let public = oracle.public_key();
+    oracle.is_conforming(&((c * s.modpow(&public.e, &public.n)) % &public.n))
+}
+
+/// Step 3: narrows every interval of M given a conforming multiplier s
+fn narrow_intervals(intervals: &[(BigUint, BigUint)], s: &BigUint, n: &BigUint, b2: &BigUint, b3: &BigUint) -> Vec<(BigUint, BigUint)> {
+    let mut narrowed: Vec<(BigUint, BigUint)> = Vec::new();
+    for (a, b) in intervals {
+        let lowest = a * s + 1u32;
+        let mut r = if lowest > *b3 { ceil_div(&(lowest - b3), n) } else { BigUint::from(0u32) };
+        let r_max = (b * s - b2) / n;
+
+        while r <= r_max {
+            let new_a = ceil_div(&(b2 + &r * n), s).max(a.clone());
+            let new_b = ((b3 - 1u32 + &r * n) / s).min(b.clone());
+            if new_a <= new_b {
+                narrowed.push((new_a, new_b));
+            }
+            r += 1u32;
+        }
+    }
+
+    narrowed.sort();
+    let mut merged: Vec<(BigUint, BigUint)> = Vec::with_capacity(narrowed.len());
+    for (a, b) in narrowed {
+        if let Some(last) = merged.last_mut() {
+            if a <= last.1 {
+                if b > last.1 {
+                    last.1 = b;
+                }
+                continue;
+            }
+        }
+        merged.push((a, b));
+    }
+    merged
+}
+
+/// Bleichenbacher's 1998 adaptive chosen-ciphertext attack. `c` must decrypt to a PKCS#1 conforming block
+/// (as produced by `encrypt`), the full padded block is returned. `progress` is called after each step 3.
+pub fn bleichenbacher_attack<F>(oracle: &PaddingOracle, c: &BigUint, mut progress: F) -> BigUint
+    where F: FnMut(&BleichenbacherProgress)
+{
+    let n = oracle.public_key().n.clone();
+    let k = modulus_len(&n);
+    let b = BigUint::one() << (8 * (k - 2));
+    let b2 = &b * 2u32;
+    let b3 = &b * 3u32;
+
+    let mut intervals = vec![(b2.clone(), &b3 - 1u32)];
+    let mut s = BigUint::from(0u32);
+    let mut iteration = 1;
+
+    loop {
+        if iteration == 1 {
+            // Step 2a
+            s = ceil_div(&n, &b3);
+            while !conforming_multiplier(oracle, c, &s) {
+                s += 1u32;
+            }
+        } else if intervals.len() > 1 {
+            // Step 2b
+            s += 1u32;
+            while !conforming_multiplier(oracle, c, &s) {
+                s += 1u32;
+            }
+        } else {
+            // Step 2c
+            let (ref a, ref upper) = intervals[0];
+            let mut r = ceil_div(&((upper * &s - &b2) * 2u32), &n);
+            'search: loop {
+                let s_min = ceil_div(&(&b2 + &r * &n), upper);
+                let s_max = ceil_div(&(&b3 + &r * &n), a);
+                let mut candidate = s_min;
+                while candidate < s_max {
+                    if conforming_multiplier(oracle, c, &candidate) {
+                        s = candidate;
+                        break 'search;
+                    }
+                    candidate += 1u32;
+                }
+                r += 1u32;
+            }
+        }
+
+        intervals = narrow_intervals(&intervals, &s, &n, &b2, &b3);
+        progress(&BleichenbacherProgress { iteration, queries: oracle.queries(), intervals: &intervals });
+
+        // Step 4
+        if intervals.len() == 1 && intervals[0].0 == intervals[0].1 {
+            return intervals[0].0.clone();
+        }
+        iteration += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sign_and_verify_work() {
+        let keypair = rsa::generate_keypair(1024, rsa::DEFAULT_E);
+        for &alg in [HashAlgorithm::Sha1, HashAlgorithm::Sha256].iter() {
+            let signature = sign(&keypair.private, alg, b"hi mom");
+            assert!(verify_sloppy(&keypair.public, alg, b"hi mom", &signature));
+            assert!(verify_strict(&keypair.public, alg, b"hi mom", &signature));
+            assert!(!verify_sloppy(&keypair.public, alg, b"hi dad", &signature));
+            assert!(!verify_strict(&keypair.public, alg, b"hi dad", &signature));
+        }
+    }
+
+    #[test]
+    fn forge_signature_works() {
+        let keypair = rsa::generate_keypair(1024, 3);
+        let forged = forge_signature(&keypair.public, HashAlgorithm::Sha1, b"hi mom").unwrap();
+        assert!(verify_sloppy(&keypair.public, HashAlgorithm::Sha1, b"hi mom", &forged));
+        assert!(!verify_strict(&keypair.public, HashAlgorithm::Sha1, b"hi mom", &forged));
+    }
+
+    #[test]
+    fn encryption_padding_works() {
+        let keypair = rsa::generate_keypair(512, rsa::DEFAULT_E);
+        let c = encrypt(&keypair.public, b"kick it, CC").unwrap();
+        assert_eq!(Some(b"kick it, CC".to_vec()), decrypt(&keypair.private, &c));
+        assert_eq!(None, encrypt(&keypair.public, &[0x41; 54]));
+
+        let block = pad_encryption_block(b"", 16).unwrap();
+        assert_eq!(&[0x00, 0x02], &block[..2]);
+        assert!(block[2..15].iter().all(|&b| b != 0));
+        assert_eq!(0x00, block[15]);
+        assert_eq!(None, unpad_encryption_block(&[0x00, 0x02, 0x01, 0x00, 0x41]));
+    }
+
+    fn check_bleichenbacher_attack(bits: usize, msg: &[u8]) {
+        let oracle = PaddingOracle::new(bits);
+        let c = encrypt(oracle.public_key(), msg).unwrap();
+        assert!(oracle.is_conforming(&c));
+
+        let mut iterations = 0;
+        let block = bleichenbacher_attack(&oracle, &c, |progress| iterations = progress.iteration);
+        let block = rsa::int_to_bytes_padded(&block, bits / 8);
+        assert_eq!(Some(msg.to_vec()), unpad_encryption_block(&block));
+        assert!(iterations > 0);
+        assert!(oracle.queries() > 1);
+    }
+
+    #[test]
+    fn bleichenbacher_attack_works() {
+        check_bleichenbacher_attack(256, b"kick it, CC");
+    }
+
+    #[test]
+    fn bleichenbacher_attack_768_works() {
+        check_bleichenbacher_attack(768, b"kick it, CC");
+    }
+
+    #[test]
+    fn forge_signature_requires_small_e() {
+        let keypair = rsa::generate_keypair(1024, rsa::DEFAULT_E);
+        assert_eq!(None, forge_signature(&keypair.public, HashAlgorithm::Sha1, b"hi mom"));
+    }
+}
@@ -0,0 +1,199 @@
+use std::thread;
+
+use util;
+
+const KEY_SIZE: usize = 16;
+
+pub struct Rc4 {
+    state: [u8; 256],
+    i: u8,
+    j: u8,
+}
+
+impl Rc4 {
+    pub fn new(key: &[u8]) -> Rc4 {
+        assert!(!key.is_empty() && key.len() <= 256);
+        let mut state = [0u8; 256];
+        for (i, s) in state.iter_mut().enumerate() {
+            *s = i as u8;
+        }
+        let mut j = 0u8;
+        for i in 0..256 {
+            j = j.wrapping_add(state[i]).wrapping_add(key[i % key.len()]);
+            state.swap(i, j as usize);
+        }
+        Rc4 { state, i: 0, j: 0 }
+    }
+
+    pub fn next_byte(&mut self) -> u8 {
+        self.i = self.i.wrapping_add(1);
+        self.j = self.j.wrapping_add(self.state[self.i as usize]);
+        self.state.swap(self.i as usize, self.j as usize);
+        let t = self.state[self.i as usize].wrapping_add(self.state[self.j as usize]);
+        self.state[t as usize]
+    }
+
+    pub fn process(&mut self, data: &[u8]) -> Vec<u8> {
+        data.iter().map(|b| b ^ self.next_byte()).collect()
+    }
+}
+
+pub fn rc4_crypt(key: &[u8], data: &[u8]) -> Vec<u8> {
+    Rc4::new(key).process(data)
+}
+
+/// Encrypts attacker-chosen request prefixes followed by a secret cookie, under a fresh key every time
+pub struct CookieOracle {
+    cookie: Vec<u8>,
+}
+
+impl CookieOracle {
+    pub fn new(cookie: &[u8]) -> CookieOracle {
+        CookieOracle { cookie: cookie.to_vec() }
+    }
+
+    pub fn encrypt(&self, request: &[u8]) -> Vec<u8> {
+        let mut plaintext = request.to_vec();
+        plaintext.extend_from_slice(&self.cookie);
+        rc4_crypt(&util::generate_random_bytes(KEY_SIZE), &plaintext)
+    }
+}
+
+/// The keystream byte at (zero-based) `position` equals `value` noticeably more often than 1/256
+#[derive(Clone, Copy, Debug)]
+pub struct KeystreamBias {
+    pub position: usize,
+    pub value: u8,
+}
+
+/// Mantin-Shamir: the second byte is 0 with probability 2/256
+pub const Z2_BIAS: KeystreamBias = KeystreamBias { position: 1, value: 0 };
+pub const Z16_BIAS: KeystreamBias = KeystreamBias { position: 15, value: 240 };
+pub const Z32_BIAS: KeystreamBias = KeystreamBias { position: 31, value: 224 };
+
+#[derive(Clone, Copy, Debug)]
+pub struct ByteCandidate {
+    pub byte: u8,
+    pub likelihood: f64,
+}
+
+/// Ciphertext byte histograms at `positions`, gathered from `samples` encryptions split across `threads`
+/// (at least one)
+fn count_ciphertext_bytes(oracle: &CookieOracle, prefix_len: usize, positions: &[usize], samples: usize, threads: usize) -> Vec<[u64; 256]> {
+    let prefix = vec![b'A'; prefix_len];
+    let threads = threads.max(1);
+    let per_thread = samples.div_ceil(threads);
+
+    thread::scope(|scope| {
+        let handles: Vec<_> = (0..threads)
+            .map(|_| scope.spawn(|| {
+                let mut counts = vec![[0u64; 256]; positions.len()];
+                for _ in 0..per_thread {
+                    let ciphertext = oracle.encrypt(&prefix);
+                    for (count, &position) in counts.iter_mut().zip(positions) {
+                        count[ciphertext[position] as usize] += 1;
+                    }
+                }
+                counts
+            }))
+            .collect();
+
+        let mut totals = vec![[0u64; 256]; positions.len()];
+        for handle in handles {
+            for (total, count) in totals.iter_mut().zip(handle.join().unwrap()) {
+                for (t, c) in total.iter_mut().zip(count.iter()) {
+                    *t += c;
+                }
+            }
+        }
+        totals
+    })
+}
+
+/// Relative frequency of c ^ bias.value for every plaintext candidate c, added up into `scores`
+fn score_for_counts(counts: &[u64; 256], bias: &KeystreamBias, scores: &mut [f64; 256]) {
+    let total: u64 = counts.iter().sum();
+    for (c, score) in scores.iter_mut().enumerate() {
+        *score += counts[c ^ bias.value as usize] as f64 / total as f64;
+    }
+}
+
+/// Single-byte bias attack: for every cookie byte, candidates ordered by decreasing likelihood. Only the
+/// bytes some bias position can be lined up with (those before the last bias position) are recovered,
+/// the list stops at the first byte no bias covers.
+pub fn recover_cookie(oracle: &CookieOracle, biases: &[KeystreamBias], samples: usize, threads: usize) -> Vec<Vec<ByteCandidate>> {
+    let cookie_len = oracle.encrypt(&[]).len();
+    let max_position = biases.iter().map(|bias| bias.position).max().unwrap_or(0);
+    let mut scores = vec![[0f64; 256]; cookie_len];
+    let mut covered = vec![false; cookie_len];
+
+    // with prefix_len bytes in front, bias.position lines up with cookie byte bias.position - prefix_len
+    for prefix_len in 0..=max_position {
+        let usable: Vec<&KeystreamBias> = biases.iter()
+            .filter(|bias| bias.position >= prefix_len && bias.position - prefix_len < cookie_len)
+            .collect();
+        if usable.is_empty() {
+            continue;
+        }
+        let positions: Vec<usize> = usable.iter().map(|bias| bias.position).collect();
+        let counts = count_ciphertext_bytes(oracle, prefix_len, &positions, samples, threads);
+        for (bias, count) in usable.iter().zip(counts.iter()) {
+            let index = bias.position - prefix_len;
+            score_for_counts(count, bias, &mut scores[index]);
+            covered[index] = true;
+        }
+    }
+
+    scores.iter()
+        .zip(covered.iter())
+        .take_while(|&(_, &covered)| covered)
+        .map(|(score, _)| {
+            let mut candidates: Vec<ByteCandidate> = score.iter()
+                .enumerate()
+                .map(|(byte, &likelihood)| ByteCandidate { byte: byte as u8, likelihood })
+                .collect();
+            candidates.sort_by(|a, b| b.likelihood.partial_cmp(&a.likelihood).unwrap());
+            candidates
+        })
+        .collect()
+}
+
+/// The most likely byte at every position
+pub fn best_guess(candidates: &[Vec<ByteCandidate>]) -> Vec<u8> {
+    candidates.iter().map(|c| c[0].byte).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rc4_works() {
+        // RFC 6229 test vector, 40-bit key
+        let keystream = rc4_crypt(&[0x01, 0x02, 0x03, 0x04, 0x05], &[0; 16]);
+        assert_eq!("b2396305f03dc027ccc3524a0a1118a8", util::binary_to_hex(&keystream));
+        let plaintext = b"Attack at dawn";
+        assert_eq!(plaintext.to_vec(), rc4_crypt(b"Secret", &rc4_crypt(b"Secret", plaintext)));
+    }
+
+    #[test]
+    fn recover_cookie_works() {
+        let oracle = CookieOracle::new(b"QE");
+        let candidates = recover_cookie(&oracle, &[Z2_BIAS], 1 << 16, 2);
+        assert_eq!(2, candidates.len());
+        assert_eq!(b"QE".to_vec(), best_guess(&candidates));
+        assert!(candidates[0][0].likelihood > candidates[0][1].likelihood);
+
+        // a thread count of zero still runs on one thread
+        assert_eq!(2, recover_cookie(&oracle, &[Z2_BIAS], 1 << 8, 0).len());
+    }
+
+    #[test]
+    #[ignore = "needs millions of samples, run with --release --ignored"]
+    fn recover_cookie_with_later_biases_works() {
+        // the Z16 and Z32 biases are weaker, so a single byte cookie needs millions of samples
+        let oracle = CookieOracle::new(b"B");
+        let candidates = recover_cookie(&oracle, &[Z16_BIAS, Z32_BIAS], 1 << 22, 4);
+        assert_eq!(b"B".to_vec(), best_guess(&candidates));
+    }
+}
@@ -0,0 +1,299 @@
+use std::collections::HashSet;
+
+use num_bigint::BigUint;
+use openssl::sha;
+use num_integer::Integer;
+use num_traits::{One, ToPrimitive, Zero};
+
+use numtheory;
+use util;
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct RsaPublicKey {
+    pub e: BigUint,
+    pub n: BigUint,
+}
+
+#[derive(Clone, Debug)]
+pub struct RsaPrivateKey {
+    pub d: BigUint,
+    pub n: BigUint,
+    p: BigUint,
+    q: BigUint,
+    d_p: BigUint,
+    d_q: BigUint,
+    q_inv: BigUint,
+}
+
+pub struct RsaKeyPair {
+    pub public: RsaPublicKey,
+    pub private: RsaPrivateKey,
+}
+
+pub const DEFAULT_E: u32 = 65537;
+
+/// Generates a textbook RSA keypair with a modulus of exactly `bits` bits
+pub fn generate_keypair(bits: usize, e: u32) -> RsaKeyPair {
+    assert!(bits >= 16 && bits.is_multiple_of(2));
+    let e = BigUint::from(e);
+
+    loop {
+        let p = numtheory::generate_prime(bits / 2);
+        let q = numtheory::generate_prime(bits / 2);
+        if let Some(keypair) = keypair_from_primes(p, q, &e) {
+            return keypair;
+        }
+    }
+}
+
+/// Keypair for the modulus pq, `None` if p = q or e isn't invertible mod (p - 1)(q - 1)
+pub fn keypair_from_primes(p: BigUint, q: BigUint, e: &BigUint) -> Option<RsaKeyPair> {
+    if p == q {
+        return None;
+    }
+    let one = BigUint::one();
+    let et = (&p - &one) * (&q - &one);
+    let d = numtheory::invmod(e, &et)?;
+
+    let n = &p * &q;
+    let d_p = &d % (&p - &one);
+    let d_q = &d % (&q - &one);
+    let q_inv = numtheory::invmod(&q, &p).unwrap();
+    Some(RsaKeyPair {
+        public: RsaPublicKey { e: e.clone(), n: n.clone() },
+        private: RsaPrivateKey { d, n, p, q, d_p, d_q, q_inv },
+    })
+}
+
+impl RsaPublicKey {
+    pub fn size_in_bytes(&self) -> usize {
+        (self.n.bits() as usize).div_ceil(8)
+    }
+
+    pub fn encrypt(&self, m: &BigUint) -> BigUint {
+        assert!(*m < self.n);
+        m.modpow(&self.e, &self.n)
+    }
+
+    pub fn verify(&self, m: &BigUint, signature: &BigUint) -> bool {
+        *signature < self.n && signature.modpow(&self.e, &self.n) == *m
+    }
+}
+
+impl RsaPrivateKey {
+    pub fn decrypt(&self, c: &BigUint) -> BigUint {
+        c.modpow(&self.d, &self.n)
+    }
+
+    /// Decryption using the Chinese Remainder Theorem with d mod (p - 1) and d mod (q - 1)
+    pub fn decrypt_crt(&self, c: &BigUint) -> BigUint {
+        let m_p = c.modpow(&self.d_p, &self.p);
+        let m_q = c.modpow(&self.d_q, &self.q);
+        let h = (&self.q_inv * (&m_p + &self.p - &m_q % &self.p)) % &self.p;
+        m_q + h * &self.q
+    }
+
+    pub fn sign(&self, m: &BigUint) -> BigUint {
+        assert!(*m < self.n);
+        self.decrypt_crt(m)
+    }
+}
+
+/// Generates `e` fresh keypairs with public exponent `e` and encrypts `m` under each public key
+pub fn generate_broadcast_scenario(m: &BigUint, e: u32, bits: usize) -> Vec<(BigUint, RsaPublicKey)> {
+    (0..e).map(|_| {
+        let keypair = generate_keypair(bits, e);
+        (keypair.public.encrypt(m), keypair.public)
+    }).collect()
+}
+
+/// Håstad's broadcast attack: recovers m from `e` encryptions of it under distinct moduli with the same small `e`
+pub fn broadcast_attack(ciphertexts: &[(BigUint, RsaPublicKey)]) -> Option<BigUint> {
+    let e = ciphertexts.first()?.1.e.to_u32()?;
+    if ciphertexts.len() < e as usize || ciphertexts.iter().any(|(_, key)| key.e != BigUint::from(e)) {
+        return None;
+    }
+
+    let used = &ciphertexts[..e as usize];
+    let residues: Vec<BigUint> = used.iter().map(|(c, _)| c.clone()).collect();
+    let moduli: Vec<BigUint> = used.iter().map(|(_, key)| key.n.clone()).collect();
+    let (m_e, _) = numtheory::crt(&residues, &moduli)?;
+
+    numtheory::exact_root(&m_e, e)
+}
+
+/// Decrypts any ciphertext once, remembering the hashes of the ciphertexts it has already seen
+pub struct DecryptionServer {
+    keypair: RsaKeyPair,
+    seen: HashSet<[u8; 32]>,
+}
+
+impl DecryptionServer {
+    pub fn new(bits: usize) -> DecryptionServer {
+        DecryptionServer { keypair: generate_keypair(bits, DEFAULT_E), seen: HashSet::new() }
+    }
+
+    pub fn public_key(&self) -> &RsaPublicKey {
+        &self.keypair.public
+    }
+
+    pub fn decrypt(&mut self, c: &BigUint) -> Option<BigUint> {
+        if !self.seen.insert(sha::sha256(&int_to_bytes(c))) {
+            return None;
+        }
+        Some(self.keypair.private.decrypt_crt(c))
+    }
+}
+
+/// Recovers the plaintext of a captured (already submitted) ciphertext by submitting s^e * c mod N instead
+pub fn unpadded_message_recovery(server: &mut DecryptionServer, c: &BigUint) -> Option<BigUint> {
+    let public = server.public_key().clone();
+    let two = BigUint::from(2u32);
+    loop {
+        let s = util::generate_random_biguint_below(&(&public.n - &two)) + &two;
+        let s_inv = match numtheory::invmod(&s, &public.n) {
+            Some(s_inv) => s_inv,
+            None => continue,
+        };
+        let blinded = (s.modpow(&public.e, &public.n) * c) % &public.n;
+        let p_blinded = server.decrypt(&blinded)?;
+        return Some((p_blinded * s_inv) % &public.n);
+    }
+}
+
+/// Decrypts ciphertexts but only reveals whether the plaintext is even
+pub struct ParityOracle {
+    keypair: RsaKeyPair,
+}
+
+impl ParityOracle {
+    pub fn new(bits: usize) -> ParityOracle {
+        ParityOracle { keypair: generate_keypair(bits, DEFAULT_E) }
+    }
+
+    pub fn public_key(&self) -> &RsaPublicKey {
+        &self.keypair.public
+    }
+
+    pub fn is_plaintext_even(&self, c: &BigUint) -> bool {
+        self.keypair.private.decrypt_crt(c).is_even()
+    }
+}
+
+/// Recovers the plaintext of `c` by doubling it log2(N) times. After i steps the plaintext lies in
+/// [a * N / 2^i, (a + 1) * N / 2^i], `a` is tracked exactly and the upper bound is passed to `progress`.
+pub fn parity_oracle_attack<F: FnMut(&BigUint)>(oracle: &ParityOracle, c: &BigUint, mut progress: F) -> BigUint {
+    let public = oracle.public_key();
+    let double = BigUint::from(2u32).modpow(&public.e, &public.n);
+    let steps = public.n.bits();
+
+    let mut c = c.clone();
+    let mut a = BigUint::zero();
+    for i in 1..=steps {
+        c = (c * &double) % &public.n;
+        a <<= 1;
+        if !oracle.is_plaintext_even(&c) {
+            a += 1u32;
+        }
+        progress(&(((&a + 1u32) * &public.n) >> i));
+    }
+
+    ((a + 1u32) * &public.n) >> steps
+}
+
+/// Progress callback for `parity_oracle_attack` printing the partially recovered plaintext
+pub fn hollywood_progress(upper_bound: &BigUint) {
+    println!("{}", String::from_utf8_lossy(&int_to_bytes(upper_bound)));
+}
+
+pub fn bytes_to_int(bytes: &[u8]) -> BigUint {
+    BigUint::from_bytes_be(bytes)
+}
+
+pub fn int_to_bytes(n: &BigUint) -> Vec<u8> {
+    n.to_bytes_be()
+}
+
+/// Big-endian bytes left-padded with zeros to `len` bytes
+pub fn int_to_bytes_padded(n: &BigUint, len: usize) -> Vec<u8> {
+    let bytes = n.to_bytes_be();
+    assert!(bytes.len() <= len);
+    let mut padded = vec![0u8; len - bytes.len()];
+    padded.extend_from_slice(&bytes);
+    padded
+}
+
+pub fn hex_to_int(hex_str: &str) -> Option<BigUint> {
+    util::hex_to_binary(hex_str).map(|bytes| bytes_to_int(&bytes))
+}
+
+pub fn int_to_hex(n: &BigUint) -> String {
+    util::binary_to_hex(&int_to_bytes(n))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rsa_encryption_works() {
+        for &e in [3, DEFAULT_E].iter() {
+            let keypair = generate_keypair(512, e);
+            assert_eq!(512, keypair.public.n.bits());
+
+            let m = bytes_to_int(b"Textbook RSA is not secure");
+            let c = keypair.public.encrypt(&m);
+            assert_eq!(m, keypair.private.decrypt(&c));
+            assert_eq!(m, keypair.private.decrypt_crt(&c));
+        }
+    }
+
+    #[test]
+    fn rsa_signature_works() {
+        let keypair = generate_keypair(512, DEFAULT_E);
+        let m = bytes_to_int(b"Sign me");
+        let signature = keypair.private.sign(&m);
+        assert!(keypair.public.verify(&m, &signature));
+        assert!(!keypair.public.verify(&bytes_to_int(b"Sign me!"), &signature));
+    }
+
+    #[test]
+    fn broadcast_attack_works() {
+        let m = bytes_to_int(b"Attack at dawn");
+        assert_eq!(Some(m.clone()), broadcast_attack(&generate_broadcast_scenario(&m, 3, 256)));
+        assert_eq!(Some(m.clone()), broadcast_attack(&generate_broadcast_scenario(&m, 5, 128)));
+
+        let scenario = generate_broadcast_scenario(&m, 3, 256);
+        assert_eq!(None, broadcast_attack(&scenario[..2]));
+    }
+
+    #[test]
+    fn unpadded_message_recovery_works() {
+        let mut server = DecryptionServer::new(512);
+        let m = bytes_to_int(b"{time: 1356304276, social: '555-55-5555'}");
+        let c = server.public_key().encrypt(&m);
+        assert_eq!(Some(m.clone()), server.decrypt(&c));
+        assert_eq!(None, server.decrypt(&c));
+
+        assert_eq!(Some(m), unpadded_message_recovery(&mut server, &c));
+    }
+
+    #[test]
+    fn parity_oracle_attack_works() {
+        let oracle = ParityOracle::new(512);
+        let m = bytes_to_int(b"Don't play around with the Funky Cold Medina");
+        let c = oracle.public_key().encrypt(&m);
+
+        let mut steps = 0;
+        assert_eq!(m, parity_oracle_attack(&oracle, &c, |_| steps += 1));
+        assert_eq!(512, steps);
+    }
+
+    #[test]
+    fn int_conversions_work() {
+        let n = hex_to_int("0102ff").unwrap();
+        assert_eq!(BigUint::from(0x0102ffu32), n);
+        assert_eq!("0102ff", int_to_hex(&n));
+        assert_eq!(vec![0, 0, 1, 2, 0xff], int_to_bytes_padded(&n, 5));
+    }
+}
@@ -0,0 +1,306 @@
+use num_bigint::BigUint;
+use num_traits::Zero;
+use openssl::sha;
+
+use dh::DhParams;
+use mac;
+use util;
+
+const SALT_SIZE: usize = 16;
+const SIMPLIFIED_U_SIZE: usize = 16;
+
+#[derive(Clone, Debug)]
+pub struct SrpParams {
+    pub n: BigUint,
+    pub g: BigUint,
+    pub k: BigUint,
+}
+
+impl SrpParams {
+    /// SRP-6a parameters, the multiplier is k = H(N | PAD(g)) rather than SRP-6's fixed 3
+    pub fn new(n: BigUint, g: BigUint) -> SrpParams {
+        let k = multiplier(&n, &g);
+        SrpParams { n, g, k }
+    }
+
+    pub fn nist() -> SrpParams {
+        let dh_params = DhParams::nist();
+        SrpParams::new(dh_params.p, dh_params.g)
+    }
+}
+
+pub struct Verifier {
+    pub salt: Vec<u8>,
+    pub v: BigUint,
+}
+
+fn hash_to_int(parts: &[&[u8]]) -> BigUint {
+    let mut hasher = sha::Sha256::new();
+    for part in parts {
+        hasher.update(part);
+    }
+    BigUint::from_bytes_be(&hasher.finish())
+}
+
+/// H(N | PAD(g)), with g left padded to the length of N
+fn multiplier(n: &BigUint, g: &BigUint) -> BigUint {
+    let n_bytes = n.to_bytes_be();
+    let g_bytes = g.to_bytes_be();
+    let mut padded_g = vec![0u8; n_bytes.len().saturating_sub(g_bytes.len())];
+    padded_g.extend_from_slice(&g_bytes);
+    hash_to_int(&[&n_bytes, &padded_g])
+}
+
+fn password_exponent(salt: &[u8], password: &[u8]) -> BigUint {
+    hash_to_int(&[salt, password])
+}
+
+fn scrambling_parameter(a_public: &BigUint, b_public: &BigUint) -> BigUint {
+    hash_to_int(&[&a_public.to_bytes_be(), &b_public.to_bytes_be()])
+}
+
+fn session_key(s: &BigUint) -> Vec<u8> {
+    sha::sha256(&s.to_bytes_be()).to_vec()
+}
+
+pub fn generate_verifier(params: &SrpParams, password: &[u8]) -> Verifier {
+    let salt = util::generate_random_bytes(SALT_SIZE);
+    let x = password_exponent(&salt, password);
+    let v = params.g.modpow(&x, &params.n);
+    Verifier { salt, v }
+}
+
+pub struct SrpClient {
+    params: SrpParams,
+    email: Vec<u8>,
+    password: Vec<u8>,
+    a: BigUint,
+    a_public: BigUint,
+}
+
+impl SrpClient {
+    pub fn new(params: &SrpParams, email: &[u8], password: &[u8]) -> SrpClient {
+        let a = util::generate_random_biguint_below(&params.n);
+        let a_public = params.g.modpow(&a, &params.n);
+        SrpClient {
+            params: params.clone(),
+            email: email.to_vec(),
+            password: password.to_vec(),
+            a,
+            a_public,
+        }
+    }
+
+    pub fn start(&self) -> (Vec<u8>, BigUint) {
+        (self.email.clone(), self.a_public.clone())
+    }
+
+    /// Computes the proof HMAC-SHA256(K, salt) from the server's salt and B
+    pub fn proof(&self, salt: &[u8], b_public: &BigUint) -> Vec<u8> {
+        let n = &self.params.n;
+        let u = scrambling_parameter(&self.a_public, b_public);
+        let x = password_exponent(salt, &self.password);
+        let kv = (&self.params.k * self.params.g.modpow(&x, n)) % n;
+        let base = (b_public % n + n - kv) % n;
+        let s = base.modpow(&(&self.a + u * x), n);
+        mac::hmac_sha256(&session_key(&s), salt)
+    }
+}
+
+pub struct SrpServer {
+    params: SrpParams,
+    email: Vec<u8>,
+    verifier: Verifier,
+    expected_proof: Option<Vec<u8>>,
+}
+
+impl SrpServer {
+    pub fn new(params: &SrpParams, email: &[u8], password: &[u8]) -> SrpServer {
+        SrpServer {
+            params: params.clone(),
+            email: email.to_vec(),
+            verifier: generate_verifier(params, password),
+            expected_proof: None,
+        }
+    }
+
+    /// Handles the client's (I, A) and answers with (salt, B), or `None` for an unknown identity
+    pub fn handshake(&mut self, email: &[u8], a_public: &BigUint) -> Option<(Vec<u8>, BigUint)> {
+        if email != &self.email[..] {
+            return None;
+        }
+        let n = &self.params.n;
+        let b = util::generate_random_biguint_below(n);
+        let b_public = (&self.params.k * &self.verifier.v + self.params.g.modpow(&b, n)) % n;
+        let u = scrambling_parameter(a_public, &b_public);
+        let s = (a_public * self.verifier.v.modpow(&u, n) % n).modpow(&b, n);
+        self.expected_proof = Some(mac::hmac_sha256(&session_key(&s), &self.verifier.salt));
+        Some((self.verifier.salt.clone(), b_public))
+    }
+
+    /// Checks the proof for the last handshake, which can only be tried once
+    pub fn verify(&mut self, proof: &[u8]) -> bool {
+        match self.expected_proof.take() {
+            Some(expected) => expected[..] == *proof,
+            None => false,
+        }
+    }
+}
+
+pub fn authenticate(client: &SrpClient, server: &mut SrpServer) -> bool {
+    let (email, a_public) = client.start();
+    match server.handshake(&email, &a_public) {
+        Some((salt, b_public)) => server.verify(&client.proof(&salt, &b_public)),
+        None => false,
+    }
+}
+
+/// Logs in without the password by sending A = multiple * N, which forces the server's S to zero
+pub fn zero_key_login(params: &SrpParams, server: &mut SrpServer, email: &[u8], multiple: u32) -> bool {
+    let a_public = &params.n * BigUint::from(multiple);
+    match server.handshake(email, &a_public) {
+        Some((salt, _)) => server.verify(&mac::hmac_sha256(&session_key(&BigUint::zero()), &salt)),
+        None => false,
+    }
+}
+
+pub struct SimplifiedSrpClient {
+    params: SrpParams,
+    password: Vec<u8>,
+    a: BigUint,
+    pub a_public: BigUint,
+}
+
+impl SimplifiedSrpClient {
+    pub fn new(params: &SrpParams, password: &[u8]) -> SimplifiedSrpClient {
+        let a = util::generate_random_biguint_below(&params.n);
+        let a_public = params.g.modpow(&a, &params.n);
+        SimplifiedSrpClient { params: params.clone(), password: password.to_vec(), a, a_public }
+    }
+
+    pub fn proof(&self, salt: &[u8], b_public: &BigUint, u: &BigUint) -> Vec<u8> {
+        let x = password_exponent(salt, &self.password);
+        let s = b_public.modpow(&(&self.a + u * x), &self.params.n);
+        mac::hmac_sha256(&session_key(&s), salt)
+    }
+}
+
+pub struct SimplifiedSrpServer {
+    params: SrpParams,
+    verifier: Verifier,
+    b: BigUint,
+    u: BigUint,
+}
+
+impl SimplifiedSrpServer {
+    pub fn new(params: &SrpParams, password: &[u8]) -> SimplifiedSrpServer {
+        SimplifiedSrpServer {
+            params: params.clone(),
+            verifier: generate_verifier(params, password),
+            b: util::generate_random_biguint_below(&params.n),
+            u: BigUint::from_bytes_be(&util::generate_random_bytes(SIMPLIFIED_U_SIZE)),
+        }
+    }
+
+    /// Returns (salt, B, u)
+    pub fn handshake(&self) -> (Vec<u8>, BigUint, BigUint) {
+        let b_public = self.params.g.modpow(&self.b, &self.params.n);
+        (self.verifier.salt.clone(), b_public, self.u.clone())
+    }
+
+    pub fn verify(&self, a_public: &BigUint, proof: &[u8]) -> bool {
+        let n = &self.params.n;
+        let s = (a_public * self.verifier.v.modpow(&self.u, n) % n).modpow(&self.b, n);
+        mac::hmac_sha256(&session_key(&s), &self.verifier.salt)[..] == *proof
+    }
+}
+
+/// Parameters a malicious simplified SRP server sends: empty salt, b = 1 (so B = g) and u = 1
+pub fn malicious_simplified_handshake(params: &SrpParams) -> (Vec<u8>, BigUint, BigUint) {
+    (Vec::new(), params.g.clone(), BigUint::from(1u32))
+}
+
+/// Offline dictionary attack on a proof obtained with `malicious_simplified_handshake`:
+/// with b = 1 and u = 1 the server's S = A * g^x mod N, so every candidate password can be checked locally
+pub fn crack_simplified_srp<'a, I>(params: &SrpParams, a_public: &BigUint, proof: &[u8], dictionary: I) -> Option<Vec<u8>>
+    where I: IntoIterator<Item = &'a [u8]>
+{
+    let (salt, _, _) = malicious_simplified_handshake(params);
+    for candidate in dictionary {
+        let x = password_exponent(&salt, candidate);
+        let s = a_public * params.g.modpow(&x, &params.n) % &params.n;
+        if mac::hmac_sha256(&session_key(&s), &salt)[..] == *proof {
+            return Some(candidate.to_vec());
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EMAIL: &[u8] = b"alice@example.com";
+
+    #[test]
+    fn srp_authentication_works() {
+        let params = SrpParams::nist();
+        let mut server = SrpServer::new(&params, EMAIL, b"correct horse");
+        assert!(authenticate(&SrpClient::new(&params, EMAIL, b"correct horse"), &mut server));
+        assert!(!authenticate(&SrpClient::new(&params, EMAIL, b"battery staple"), &mut server));
+        assert!(!authenticate(&SrpClient::new(&params, b"bob@example.com", b"correct horse"), &mut server));
+    }
+
+    #[test]
+    fn multiplier_is_srp6a() {
+        let params = SrpParams::nist();
+        let expected = BigUint::parse_bytes(b"04f99e0d74ef9882cd817dd7c59eaea1af70194e52baa52a99ec0fea0097e4e1", 16).unwrap();
+        assert_eq!(expected, params.k);
+    }
+
+    #[test]
+    fn proof_cannot_be_replayed() {
+        let params = SrpParams::nist();
+        let mut server = SrpServer::new(&params, EMAIL, b"correct horse");
+        let client = SrpClient::new(&params, EMAIL, b"correct horse");
+        let (email, a_public) = client.start();
+        let (salt, b_public) = server.handshake(&email, &a_public).unwrap();
+        let proof = client.proof(&salt, &b_public);
+        assert!(server.verify(&proof));
+        assert!(!server.verify(&proof));
+    }
+
+    #[test]
+    fn zero_key_login_works() {
+        let params = SrpParams::nist();
+        let mut server = SrpServer::new(&params, EMAIL, b"correct horse");
+        for multiple in 0..3 {
+            assert!(zero_key_login(&params, &mut server, EMAIL, multiple));
+        }
+    }
+
+    #[test]
+    fn simplified_srp_works() {
+        let params = SrpParams::nist();
+        let server = SimplifiedSrpServer::new(&params, b"correct horse");
+        let (salt, b_public, u) = server.handshake();
+
+        let client = SimplifiedSrpClient::new(&params, b"correct horse");
+        assert!(server.verify(&client.a_public, &client.proof(&salt, &b_public, &u)));
+
+        let client = SimplifiedSrpClient::new(&params, b"battery staple");
+        assert!(!server.verify(&client.a_public, &client.proof(&salt, &b_public, &u)));
+    }
+
+    #[test]
+    fn crack_simplified_srp_works() {
+        let params = SrpParams::nist();
+        let client = SimplifiedSrpClient::new(&params, b"sunshine");
+        let (salt, b_public, u) = malicious_simplified_handshake(&params);
+        let proof = client.proof(&salt, &b_public, &u);
+
+        let dictionary: Vec<&[u8]> = vec![b"password", b"123456", b"qwerty", b"sunshine", b"letmein"];
+        assert_eq!(Some(b"sunshine".to_vec()), crack_simplified_srp(&params, &client.a_public, &proof, dictionary.clone()));
+        assert_eq!(None, crack_simplified_srp(&params, &client.a_public, &proof, dictionary[..3].to_vec()));
+    }
+}
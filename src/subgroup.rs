@@ -0,0 +1,209 @@
+use std::cell::Cell;
+
+use num_bigint::BigUint;
+use num_integer::Integer;
+use num_traits::{One, Zero};
+
+use dh::{DhKeyPair, DhParams};
+use dlog;
+use dlog::{DlogStats, ModP};
+use mac;
+use numtheory;
+use util;
+
+const P_DEC: &str = "7199773997391911030609999317773941274322764333428698921736339643928346453700085358802973900485592910475480089726140708102474957429903531369589969318716771";
+const G_DEC: &str = "4565356397095740655436854503483826832136106141639563487732438195343690437606117828318042418238184896212352329118608100083187535033402010599512641674644143";
+const Q_DEC: &str = "236234353446506858198510045061214171961";
+const PARTIAL_P_DEC: &str = "11470374874925275658116663507232161402086650258453896274534991676898999262641581519101074740642369848233294239851519212341844337347119899874391456329785623";
+const PARTIAL_G_DEC: &str = "622952335333961296978159266084741085889881358738459939978290179936063635566740258555167783009058567397963466103140082647486611657350811560630587013183357";
+const PARTIAL_Q_DEC: &str = "335062023296420808191071248367701059461";
+const MAC_MESSAGE: &[u8] = b"crazy flamboyant for the rap enjoyment";
+pub const DEFAULT_FACTOR_BOUND: u64 = 1 << 16;
+
+/// DH group where g generates a subgroup of prime order q, much smaller than p - 1
+#[derive(Clone, Debug)]
+pub struct SubgroupParams {
+    pub dh: DhParams,
+    pub q: BigUint,
+}
+
+impl SubgroupParams {
+    pub fn new(p: BigUint, g: BigUint, q: BigUint) -> SubgroupParams {
+        SubgroupParams { dh: DhParams::new(p, g), q }
+    }
+
+    /// 512-bit p with a 128-bit q and (p - 1) / q smooth enough to recover the whole key
+    pub fn weak() -> SubgroupParams {
+        SubgroupParams::new(
+            BigUint::parse_bytes(P_DEC.as_bytes(), 10).unwrap(),
+            BigUint::parse_bytes(G_DEC.as_bytes(), 10).unwrap(),
+            BigUint::parse_bytes(Q_DEC.as_bytes(), 10).unwrap())
+    }
+
+    /// Like `weak`, but the small factors of (p - 1) / q only cover about 89 of the 128 key bits
+    pub fn partially_smooth() -> SubgroupParams {
+        SubgroupParams::new(
+            BigUint::parse_bytes(PARTIAL_P_DEC.as_bytes(), 10).unwrap(),
+            BigUint::parse_bytes(PARTIAL_G_DEC.as_bytes(), 10).unwrap(),
+            BigUint::parse_bytes(PARTIAL_Q_DEC.as_bytes(), 10).unwrap())
+    }
+}
+
+pub fn mac_with_secret(shared_secret: &BigUint, msg: &[u8]) -> Vec<u8> {
+    mac::hmac_sha256(&shared_secret.to_bytes_be(), msg)
+}
+
+/// Bob: answers any public key with a message MACed under the shared secret, without checking that the
+/// key belongs to the subgroup of order q
+pub struct MacResponder {
+    params: SubgroupParams,
+    keypair: DhKeyPair,
+    queries: Cell<u64>,
+}
+
+impl MacResponder {
+    pub fn new(params: &SubgroupParams) -> MacResponder {
+        let keypair = params.dh.generate_subgroup_keypair(&params.q);
+        MacResponder { params: params.clone(), keypair, queries: Cell::new(0) }
+    }
+
+    pub fn public_key(&self) -> &BigUint {
+        &self.keypair.public
+    }
+
+    pub fn queries(&self) -> u64 {
+        self.queries.get()
+    }
+
+    pub fn respond(&self, other_public: &BigUint) -> (Vec<u8>, Vec<u8>) {
+        self.queries.set(self.queries.get() + 1);
+        let secret = self.params.dh.shared_secret(&self.keypair, other_public);
+        (MAC_MESSAGE.to_vec(), mac_with_secret(&secret, MAC_MESSAGE))
+    }
+
+    /// Checks a guess, so callers can confirm the recovered key
+    pub fn is_private_key(&self, x: &BigUint) -> bool {
+        self.params.dh.g.modpow(x, &self.params.dh.p) == self.keypair.public
+    }
+}
+
+/// Distinct prime factors of `n` below `bound`, by trial division
+pub fn small_factors(n: &BigUint, bound: u64) -> Vec<u64> {
+    let mut n = n.clone();
+    let mut factors = Vec::new();
+    for r in 2..bound {
+        let r_big = BigUint::from(r);
+        if (&n % &r_big).is_zero() {
+            factors.push(r);
+            while (&n % &r_big).is_zero() {
+                n /= &r_big;
+            }
+        }
+    }
+    factors
+}
+
+/// Element of order r: a random element raised to (p - 1) / r, retried until it isn't 1
+pub fn element_of_order(p: &BigUint, r: u64) -> BigUint {
+    let exponent = (p - 1u32) / r;
+    loop {
+        let h = util::generate_random_biguint_below(p).modpow(&exponent, p);
+        if !h.is_one() {
+            return h;
+        }
+    }
+}
+
+/// x mod r from a MAC keyed by h^x, trying every b < r
+fn brute_force_residue(p: &BigUint, h: &BigUint, r: u64, msg: &[u8], tag: &[u8]) -> Option<u64> {
+    let mut candidate = BigUint::one();
+    for b in 0..r {
+        if mac_with_secret(&candidate, msg) == tag {
+            return Some(b);
+        }
+        candidate = (candidate * h) % p;
+    }
+    None
+}
+
+/// Pohlig-Hellman through the responder: x mod r for small factors r of (p - 1) / q, combined with CRT.
+/// Stops once the moduli product exceeds q. Returns (x mod m, m).
+pub fn small_subgroup_residues(params: &SubgroupParams, responder: &MacResponder, factor_bound: u64) -> Option<(BigUint, BigUint)> {
+    let p = &params.dh.p;
+    let j = (p - 1u32) / &params.q;
+    let mut residues = Vec::new();
+    let mut moduli = Vec::new();
+
+    for r in small_factors(&j, factor_bound) {
+        let h = element_of_order(p, r);
+        let (msg, tag) = responder.respond(&h);
+        residues.push(BigUint::from(brute_force_residue(p, &h, r, &msg, &tag)?));
+        moduli.push(BigUint::from(r));
+        if moduli.iter().product::<BigUint>() > params.q {
+            break;
+        }
+    }
+
+    numtheory::crt(&residues, &moduli)
+}
+
+/// Recovers the whole private key when the small factors of (p - 1) / q multiply to more than q
+pub fn recover_private_key(params: &SubgroupParams, responder: &MacResponder) -> Option<BigUint> {
+    let (x, modulus) = small_subgroup_residues(params, responder, DEFAULT_FACTOR_BOUND)?;
+    if modulus > params.q {
+        Some(x.mod_floor(&params.q))
+    } else {
+        None
+    }
+}
+
+/// Small subgroup residues for as many bits as they give, then a kangaroo walk for the rest of the key
+pub fn recover_private_key_with_kangaroo(params: &SubgroupParams, responder: &MacResponder, stats: &mut DlogStats) -> Option<BigUint> {
+    let (n, r) = small_subgroup_residues(params, responder, DEFAULT_FACTOR_BOUND)?;
+    if r > params.q {
+        return Some(n.mod_floor(&params.q));
+    }
+    let group = ModP { p: &params.dh.p };
+    dlog::complete_partial_key(&group, &params.dh.g, responder.public_key(), &params.q, &n, &r, stats)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn small_factors_works() {
+        assert_eq!(vec![2, 3, 5], small_factors(&BigUint::from(2u32 * 2 * 3 * 5 * 5 * 101), 100));
+        assert!(small_factors(&BigUint::from(7919u32), 100).is_empty());
+    }
+
+    #[test]
+    fn element_of_order_works() {
+        let params = SubgroupParams::weak();
+        let p = &params.dh.p;
+        let h = element_of_order(p, 7963);
+        assert!(!h.is_one());
+        assert!(h.modpow(&BigUint::from(7963u32), p).is_one());
+    }
+
+    #[test]
+    fn recover_private_key_works() {
+        let params = SubgroupParams::weak();
+        let responder = MacResponder::new(&params);
+        let x = recover_private_key(&params, &responder).unwrap();
+        assert!(responder.is_private_key(&x));
+        assert!(responder.queries() > 0);
+    }
+
+    #[test]
+    fn recover_private_key_with_kangaroo_works() {
+        let params = SubgroupParams::partially_smooth();
+        let responder = MacResponder::new(&params);
+        assert_eq!(None, recover_private_key(&params, &responder));
+
+        let mut stats = DlogStats::default();
+        let x = recover_private_key_with_kangaroo(&params, &responder, &mut stats).unwrap();
+        assert!(responder.is_private_key(&x));
+        assert!(stats.iterations > 0);
+    }
+}
@@ -0,0 +1,241 @@
+use std::cell::Cell;
+use std::cmp;
+
+use bitmatrix;
+use bitmatrix::BitMatrix;
+use block;
+use gcm;
+use gcm::GcmMessage;
+use gf128::Gf128;
+use util;
+
+pub const TRUNCATED_TAG_SIZE: usize = 4;
+/// Coefficients H^2 .. H^(2^17) are free to change, which takes 2^17 ciphertext blocks
+pub const DEFAULT_FREE_BLOCKS: usize = 17;
+/// Forgery attempts the attack makes for one round of equations before giving up
+pub const MAX_QUERIES_PER_ROUND: u64 = 1 << 20;
+
+/// AES-GCM that only keeps the first `tag_size` bytes of the tag. Also the receiving end that checks tags.
+pub struct TruncatedGcm {
+    key: Vec<u8>,
+    tag_size: usize,
+    queries: Cell<u64>,
+}
+
+impl TruncatedGcm {
+    pub fn new(tag_size: usize) -> TruncatedGcm {
+        assert!(tag_size > 0 && tag_size <= gcm::TAG_SIZE);
+        TruncatedGcm { key: util::generate_random_bytes(block::BLOCK_SIZE), tag_size, queries: Cell::new(0) }
+    }
+
+    pub fn queries(&self) -> u64 {
+        self.queries.get()
+    }
+
+    pub fn encrypt(&self, plaintext: &[u8], aad: &[u8]) -> GcmMessage {
+        let nonce = util::generate_random_bytes(gcm::NONCE_SIZE);
+        let (ciphertext, mut tag) = gcm::gcm_encrypt(&self.key, &nonce, plaintext, aad).unwrap();
+        tag.truncate(self.tag_size);
+        GcmMessage { nonce, aad: aad.to_vec(), ciphertext, tag }
+    }
+
+    pub fn decrypt(&self, msg: &GcmMessage) -> Option<Vec<u8>> {
+        self.queries.set(self.queries.get() + 1);
+        let tag = gcm::compute_tag(&self.key, &msg.nonce, &msg.aad, &msg.ciphertext)?;
+        if tag[..self.tag_size] != msg.tag[..] {
+            return None;
+        }
+        gcm::gcm_decrypt(&self.key, &msg.nonce, &msg.ciphertext, &msg.aad, &tag)
+    }
+
+    pub fn is_authentication_key(&self, h: Gf128) -> bool {
+        gcm::authentication_key(&self.key) == Some(h)
+    }
+}
+
+/// Ciphertext blocks a message needs for `free_blocks` changeable coefficients, `None` if that overflows
+pub fn blocks_needed(free_blocks: usize) -> Option<usize> {
+    1usize.checked_shl(free_blocks as u32).filter(|&n| n != 0).map(|n| n - 1)
+}
+
+/// Splits a vector of `free_blocks` * 128 bits into the differences d_1 .. d_n
+fn differences(v: &[u64], free_blocks: usize) -> Vec<Gf128> {
+    (0..free_blocks).map(|i| Gf128((v[2 * i + 1] as u128) << 64 | v[2 * i] as u128)).collect()
+}
+
+/// Ad = sum of M_(d_i) * S^i, so that the tag changes by Ad * h when the block multiplied by H^(2^i) changes by d_i
+fn error_matrix(ds: &[Gf128], squarings: &[BitMatrix]) -> BitMatrix {
+    let mut ad = BitMatrix::new(128, 128);
+    for (&d, s) in ds.iter().zip(squarings) {
+        ad = &ad + &(&bitmatrix::multiplication_matrix(d) * s);
+    }
+    ad
+}
+
+/// The message with d_i XORed into the ciphertext block whose GHASH coefficient is H^(2^i)
+fn apply_differences(msg: &GcmMessage, ds: &[Gf128]) -> GcmMessage {
+    let mut forged = msg.clone();
+    let blocks = msg.ciphertext.len() / block::BLOCK_SIZE;
+    for (i, d) in ds.iter().enumerate() {
+        // the length block is multiplied by H, the last ciphertext block by H^2 and so on
+        let start = (blocks + 1 - (1 << (i + 1))) * block::BLOCK_SIZE;
+        for (c, x) in forged.ciphertext[start..start + block::BLOCK_SIZE].iter_mut().zip(d.to_block()) {
+            *c ^= x;
+        }
+    }
+    forged
+}
+
+/// Ferguson's attack on short tags. Squaring is linear over GF(2), so changing only the blocks multiplied by
+/// H^(2^i) changes the tag by Ad * h for a matrix Ad linear in the differences. Differences that zero the first
+/// rows of Ad (restricted to the space h is known to lie in) give forgeries far more often than 2^-tag_bits,
+/// and every forgery the receiver accepts adds the remaining tag rows of Ad as equations on h.
+/// `None` if the message is too short or not block aligned, or a round finds no forgery within `max_queries`.
+pub fn recover_authentication_key(receiver: &TruncatedGcm, msg: &GcmMessage, free_blocks: usize, max_queries: u64) -> Option<Gf128> {
+    let needed = blocks_needed(free_blocks)?.checked_mul(block::BLOCK_SIZE)?;
+    if free_blocks == 0 || msg.tag.is_empty() || msg.ciphertext.len() < needed || !msg.ciphertext.len().is_multiple_of(block::BLOCK_SIZE) {
+        return None;
+    }
+    let tag_bits = msg.tag.len() * 8;
+    let free_bits = free_blocks * 128;
+
+    let squaring = bitmatrix::squaring_matrix();
+    let mut squarings = vec![squaring.clone()];
+    for i in 1..free_blocks {
+        squarings.push(&squaring * &squarings[i - 1]);
+    }
+    // rows of M_(x^j) that end up in the zeroed part of Ad
+    let units: Vec<BitMatrix> = (0..128).map(|j| bitmatrix::multiplication_matrix(Gf128(1 << j))).collect();
+
+    let mut equations = BitMatrix::new(0, 128);
+    let mut basis = BitMatrix::identity(128);
+    while basis.rows() > 1 {
+        let dimension = basis.rows();
+        let x = basis.transpose();
+        let zero_rows = cmp::min(tag_bits - 1, (free_bits - 1) / dimension);
+
+        // dependency matrix: column (i, j) holds the first rows of M_(x^j) * S^i * X, flattened
+        let mut dependency = BitMatrix::new(zero_rows * dimension, free_bits);
+        for (i, s) in squarings.iter().enumerate() {
+            let projected = s * &x;
+            for (j, unit) in units.iter().enumerate() {
+                let entries = &unit.row_range(0, zero_rows) * &projected;
+                for r in 0..zero_rows {
+                    for c in 0..dimension {
+                        if entries.get(r, c) {
+                            dependency.set(r * dimension + c, i * 128 + j, true);
+                        }
+                    }
+                }
+            }
+        }
+        let kernel = dependency.kernel();
+
+        let mut queries = 0;
+        loop {
+            if queries == max_queries {
+                return None;
+            }
+            let v = kernel.random_combination();
+            if v.iter().all(|&w| w == 0) {
+                continue;
+            }
+            queries += 1;
+            let ds = differences(&v, free_blocks);
+            if receiver.decrypt(&apply_differences(msg, &ds)).is_some() {
+                let ad = error_matrix(&ds, &squarings);
+                for r in 0..tag_bits {
+                    equations.push_row(ad.row(r));
+                }
+                break;
+            }
+        }
+        basis = equations.kernel();
+    }
+    if basis.rows() == 1 {
+        let h = basis.row(0);
+        Some(Gf128((h[1] as u128) << 64 | h[0] as u128))
+    } else {
+        None
+    }
+}
+
+/// A valid truncated tag for new associated data and ciphertext under the nonce of a known message
+pub fn forge_message(h: Gf128, known: &GcmMessage, aad: &[u8], ciphertext: &[u8]) -> GcmMessage {
+    let mask = gcm::ghash(h, &known.aad, &known.ciphertext).to_block();
+    let mut tag = util::xor_bufs(&gcm::ghash(h, aad, ciphertext).to_block(), &mask);
+    tag.truncate(known.tag.len());
+    let tag = util::xor_bufs(&tag, &known.tag);
+    GcmMessage { nonce: known.nonce.clone(), aad: aad.to_vec(), ciphertext: ciphertext.to_vec(), tag }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn truncated_tags_verify() {
+        let receiver = TruncatedGcm::new(TRUNCATED_TAG_SIZE);
+        let msg = receiver.encrypt(b"short tags save bandwidth", b"header");
+        assert_eq!(TRUNCATED_TAG_SIZE, msg.tag.len());
+        assert_eq!(Some(b"short tags save bandwidth".to_vec()), receiver.decrypt(&msg));
+
+        let mut tampered = msg.clone();
+        tampered.ciphertext[0] ^= 1;
+        assert_eq!(None, receiver.decrypt(&tampered));
+
+        let mut short_nonce = msg.clone();
+        short_nonce.nonce.truncate(8);
+        assert_eq!(None, receiver.decrypt(&short_nonce));
+    }
+
+    #[test]
+    fn error_matrix_predicts_tag_change() {
+        let receiver = TruncatedGcm::new(gcm::TAG_SIZE);
+        let msg = receiver.encrypt(&vec![0; blocks_needed(3).unwrap() * block::BLOCK_SIZE], b"");
+        let squaring = bitmatrix::squaring_matrix();
+        let fourth = &squaring * &squaring;
+        let squarings = vec![squaring.clone(), fourth.clone(), &fourth * &squaring];
+        let ds = vec![Gf128::random(), Gf128::random(), Gf128::random()];
+        let forged = apply_differences(&msg, &ds);
+        let h = gcm::authentication_key(&receiver.key).unwrap();
+        let error = gcm::ghash(h, &forged.aad, &forged.ciphertext) + gcm::ghash(h, &msg.aad, &msg.ciphertext);
+        assert_eq!(error, error_matrix(&ds, &squarings).mul_element(h));
+    }
+
+    #[test]
+    fn ferguson_attack_works() {
+        // a 16-bit tag and 2^9 blocks keep the number of queries and the matrices small
+        let receiver = TruncatedGcm::new(2);
+        let free_blocks = 9;
+        let msg = receiver.encrypt(&vec![b'A'; blocks_needed(free_blocks).unwrap() * block::BLOCK_SIZE], b"");
+        let h = recover_authentication_key(&receiver, &msg, free_blocks, MAX_QUERIES_PER_ROUND).unwrap();
+        assert!(receiver.is_authentication_key(h));
+
+        let forged = forge_message(h, &msg, b"admin", b"transfer everything");
+        assert!(receiver.decrypt(&forged).is_some());
+    }
+
+    #[test]
+    fn ferguson_attack_rejects_bad_input() {
+        assert_eq!(None, blocks_needed(64));
+        assert_eq!(Some(7), blocks_needed(3));
+
+        let receiver = TruncatedGcm::new(2);
+        let short = receiver.encrypt(&[0; 6 * block::BLOCK_SIZE], b"");
+        assert_eq!(None, recover_authentication_key(&receiver, &short, 3, MAX_QUERIES_PER_ROUND));
+        let unaligned = receiver.encrypt(&[0; 7 * block::BLOCK_SIZE + 1], b"");
+        assert_eq!(None, recover_authentication_key(&receiver, &unaligned, 3, MAX_QUERIES_PER_ROUND));
+        let msg = receiver.encrypt(&[0; 7 * block::BLOCK_SIZE], b"");
+        assert_eq!(None, recover_authentication_key(&receiver, &msg, 64, MAX_QUERIES_PER_ROUND));
+        assert_eq!(0, receiver.queries());
+    }
+
+    #[test]
+    fn ferguson_attack_gives_up_after_max_queries() {
+        let receiver = TruncatedGcm::new(gcm::TAG_SIZE);
+        let msg = receiver.encrypt(&vec![0; blocks_needed(3).unwrap() * block::BLOCK_SIZE], b"");
+        assert_eq!(None, recover_authentication_key(&receiver, &msg, 3, 10));
+        assert_eq!(10, receiver.queries());
+    }
+}
@@ -0,0 +1,204 @@
+use std::cell::Cell;
+
+use num_bigint::BigUint;
+use num_traits::Zero;
+
+use dlog;
+use dlog::DlogStats;
+use mac;
+use montgomery::MontgomeryCurve;
+use numtheory;
+use subgroup;
+use util;
+
+const MAC_MESSAGE: &[u8] = b"crazy flamboyant for the rap enjoyment";
+pub const DEFAULT_FACTOR_BOUND: u64 = 1 << 22;
+
+/// MAC keyed by a u coordinate padded to the field size
+pub fn mac_with_u(curve: &MontgomeryCurve, u: &BigUint, msg: &[u8]) -> Vec<u8> {
+    let bytes = u.to_bytes_be();
+    let mut key = vec![0u8; curve.field_bytes().saturating_sub(bytes.len())];
+    key.extend_from_slice(&bytes);
+    mac::hmac_sha256(&key, msg)
+}
+
+/// Bob doing x-only ECDH: he ladders whatever u he is sent and never checks whether it is on the curve
+/// or on its twist
+pub struct XOnlyResponder {
+    curve: MontgomeryCurve,
+    private: BigUint,
+    public: BigUint,
+    queries: Cell<u64>,
+}
+
+impl XOnlyResponder {
+    pub fn new(curve: &MontgomeryCurve) -> XOnlyResponder {
+        XOnlyResponder::with_key_bound(curve, &curve.n)
+    }
+
+    /// Private key in [1, bound) instead of [1, n), so tests can finish the attack with a short kangaroo walk
+    pub fn with_key_bound(curve: &MontgomeryCurve, bound: &BigUint) -> XOnlyResponder {
+        let private = util::generate_random_biguint_below(&(bound - 1u32)) + 1u32;
+        let public = curve.ladder(&curve.base_u(), &private);
+        XOnlyResponder { curve: curve.clone(), private, public, queries: Cell::new(0) }
+    }
+
+    pub fn public_key(&self) -> &BigUint {
+        &self.public
+    }
+
+    pub fn queries(&self) -> u64 {
+        self.queries.get()
+    }
+
+    pub fn respond(&self, other_public: &BigUint) -> (Vec<u8>, Vec<u8>) {
+        self.queries.set(self.queries.get() + 1);
+        let secret = self.curve.ladder(other_public, &self.private);
+        (MAC_MESSAGE.to_vec(), mac_with_u(&self.curve, &secret, MAC_MESSAGE))
+    }
+
+    /// Both k and n - k give the same public u, so either counts
+    pub fn is_private_key(&self, k: &BigUint) -> bool {
+        self.curve.ladder(&self.curve.base_u(), k) == self.public
+    }
+}
+
+/// Distinct odd primes below `bound` dividing the twist order exactly once, so that (order / r) * P has
+/// order r or 1. The 2-part is left out: 0 would stand for both infinity and the point of order 2.
+pub fn twist_factors(curve: &MontgomeryCurve, bound: u64) -> Vec<u64> {
+    let order = curve.twist_order();
+    subgroup::small_factors(&order, bound)
+        .into_iter()
+        .filter(|&r| r != 2 && !(&order % (BigUint::from(r) * r)).is_zero())
+        .collect()
+}
+
+/// u of a twist point of order exactly r, r a product of distinct primes from `factors`
+pub fn twist_point_of_order(curve: &MontgomeryCurve, r: &BigUint, factors: &[u64]) -> BigUint {
+    let cofactor = curve.twist_order() / r;
+    loop {
+        let u = curve.ladder(&curve.random_twist_u(), &cofactor);
+        let exact = factors.iter()
+            .filter(|&&q| (r % q).is_zero())
+            .all(|&q| !curve.ladder(&u, &(r / q)).is_zero());
+        if exact {
+            return u;
+        }
+    }
+}
+
+/// k mod r up to sign from a MAC keyed by u(kP), P of order r: u(iP) = u(-iP), so only i <= r / 2 are
+/// tried, stepping with differential additions
+fn brute_force_residue(curve: &MontgomeryCurve, u: &BigUint, r: u64, msg: &[u8], tag: &[u8]) -> Option<u64> {
+    if mac_with_u(curve, &BigUint::zero(), msg) == tag {
+        return Some(0);
+    }
+    let mut previous = u.clone();
+    let mut current = u.clone();
+    for i in 1..=r / 2 {
+        if mac_with_u(curve, &current, msg) == tag {
+            return Some(i);
+        }
+        let next = if i == 1 {
+            curve.ladder(u, &BigUint::from(2u32))
+        } else {
+            curve.differential_add(&current, u, &previous)
+        };
+        previous = current;
+        current = next;
+    }
+    None
+}
+
+/// Pohlig-Hellman on the twist: k mod r up to sign for every factor r, then the signs are lined up one
+/// factor at a time. With k = ±n mod m so far and k = ±i mod r, one more query with a point of order m * r
+/// tells CRT(n, i) from CRT(n, -i). Returns (n, m) with k = ±n mod m.
+pub fn twist_residues(curve: &MontgomeryCurve, responder: &XOnlyResponder, factor_bound: u64) -> Option<(BigUint, BigUint)> {
+    let factors = twist_factors(curve, factor_bound);
+    let mut n = BigUint::zero();
+    let mut m = BigUint::from(1u32);
+
+    for (j, &r) in factors.iter().enumerate() {
+        let r_big = BigUint::from(r);
+        let u = twist_point_of_order(curve, &r_big, &[r]);
+        let (msg, tag) = responder.respond(&u);
+        let i = BigUint::from(brute_force_residue(curve, &u, r, &msg, &tag)?);
+        if j == 0 {
+            n = i;
+            m = r_big;
+            continue;
+        }
+
+        let combined = &m * &r_big;
+        let u = twist_point_of_order(curve, &combined, &factors[..=j]);
+        let (msg, tag) = responder.respond(&u);
+        let candidates = [i.clone(), (&r_big - &i) % &r_big];
+        let (residue, _) = candidates.iter()
+            .filter_map(|i| numtheory::crt(&[n.clone(), i.clone()], &[m.clone(), r_big.clone()]))
+            .find(|(c, _)| mac_with_u(curve, &curve.ladder(&u, c), &msg) == tag)?;
+        n = residue;
+        m = combined;
+    }
+
+    Some((n, m))
+}
+
+/// Twist residues, then a kangaroo walk on the Weierstrass form for the rest of a key known to be below
+/// `key_bound`. Lifting the public u gives Y = ±kG and the residues give ±k mod m, so x = k or x = -k
+/// satisfies xG = Y. The walk runs over x + bound in [0, 2 * bound) against Y + bound * G, once for each
+/// sign of the residue.
+pub fn twist_attack(curve: &MontgomeryCurve, responder: &XOnlyResponder, factor_bound: u64, key_bound: &BigUint, stats: &mut DlogStats) -> Option<BigUint> {
+    let (n, m) = twist_residues(curve, responder, factor_bound)?;
+    let weierstrass = curve.to_weierstrass();
+    let public = weierstrass.lift_x(&curve.weierstrass_x(responder.public_key()))?;
+    let shifted = weierstrass.add(&public, &weierstrass.scalar_mul(&weierstrass.g, key_bound));
+    let interval = key_bound * 2u32;
+
+    for residue in &[n.clone(), &m - &n] {
+        let residue = (residue + key_bound) % &m;
+        let found = dlog::complete_partial_key(&weierstrass, &weierstrass.g, &shifted, &interval, &residue, &m, stats);
+        if let Some(x) = found {
+            // x - bound, taken mod n
+            let k = (x + &curve.n - key_bound % &curve.n) % &curve.n;
+            if responder.is_private_key(&k) {
+                return Some(k);
+            }
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEST_FACTOR_BOUND: u64 = 1 << 11;
+
+    #[test]
+    fn twist_factors_works() {
+        let curve = MontgomeryCurve::cryptopals();
+        assert_eq!(vec![11, 107, 197, 1621, 105143, 405373, 2323367], twist_factors(&curve, DEFAULT_FACTOR_BOUND));
+    }
+
+    #[test]
+    fn twist_residues_work() {
+        let curve = MontgomeryCurve::cryptopals();
+        let responder = XOnlyResponder::new(&curve);
+        let (n, m) = twist_residues(&curve, &responder, TEST_FACTOR_BOUND).unwrap();
+        assert_eq!(BigUint::from(11u32 * 107 * 197 * 1621), m);
+        let k = &responder.private % &m;
+        assert!(k == n || k == (&m - &n) % &m);
+        assert_eq!(7, responder.queries());
+    }
+
+    #[test]
+    fn twist_attack_works() {
+        let curve = MontgomeryCurve::cryptopals();
+        let key_bound = BigUint::from(1u64 << 48);
+        let responder = XOnlyResponder::with_key_bound(&curve, &key_bound);
+        let mut stats = DlogStats::default();
+        let k = twist_attack(&curve, &responder, TEST_FACTOR_BOUND, &key_bound, &mut stats).unwrap();
+        assert!(responder.is_private_key(&k));
+        assert!(stats.iterations > 0);
+    }
+}
@@ -1,6 +1,8 @@
 use std::fmt::Write;
 use std::u8;
 
+use num_bigint::BigUint;
+use num_traits::Zero;
 use rand::Rng;
 use rand::os;
 
@@ -78,6 +80,13 @@ pub fn generate_random_bytes(len: usize) -> Vec<u8> {
     rand_buf
 }
 
+pub fn generate_random_biguint_below(bound: &BigUint) -> BigUint {
+    assert!(!bound.is_zero());
+    let bytes_len = bound.to_bytes_be().len() + 8;
+
+    BigUint::from_bytes_be(&generate_random_bytes(bytes_len)) % bound
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;